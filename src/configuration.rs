@@ -1,25 +1,210 @@
-use clap::{App, Arg};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use clap::{App, Arg, ArgGroup, ArgMatches, Error, ErrorKind};
+
+use crate::disk::{
+    DiskAllocation, DiskFiles, DiskGrowth, DiskLayout, DiskLinks, DiskShrink, DiskSizeSemantics,
+    DiskSizes,
+};
+use crate::fd::{self, FdKind};
+use crate::memory::{GrowthMode, MemoryGrowth, MemorySemantics, MemoryStrategy};
+use crate::profile::{self, LoadProfile};
+use crate::quantity;
+use crate::restart::RestartSchedule;
+use crate::scenario::Scenario;
+use crate::trace::Trace;
+
+/// Linux truncates thread names to 15 bytes (+ the terminating null byte)
+pub const MAX_THREAD_NAME_LEN: usize = 15;
+
+/// A named thread generating the target cpu load in percent of one CPU
+#[derive(Clone, Debug)]
+pub struct ThreadLoad {
+    pub name: String,
+    pub target: f64,
+}
+
+impl FromStr for ThreadLoad {
+    type Err = String;
+
+    /// Parses a `NAME=PERCENT` pair
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, target) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Was expecting NAME=PERCENT, got {}", s))?;
+        if name.is_empty() || name.len() > MAX_THREAD_NAME_LEN {
+            return Err(format!(
+                "Thread name must be 1 to {} bytes long, got {}",
+                MAX_THREAD_NAME_LEN, name
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            target: thread_percent(target)?,
+        })
+    }
+}
+
+/// A single thread loads at most one CPU, a higher target could not be reached
+pub fn thread_percent(value: &str) -> Result<f64, String> {
+    match quantity::parse_percent(value)? {
+        percent if percent <= 100.0 => Ok(percent),
+        _ => Err(format!(
+            "A thread loads at most 100% of one CPU, got {}",
+            value
+        )),
+    }
+}
+
+/// Checks that no two of the `threads` share a name, the monitoring could not tell them apart
+pub fn check_unique_names(threads: &[ThreadLoad]) -> Result<(), String> {
+    for (index, thread) in threads.iter().enumerate() {
+        if threads[..index]
+            .iter()
+            .any(|other| other.name == thread.name)
+        {
+            return Err(format!("Thread {} is named more than once", thread.name));
+        }
+    }
+    Ok(())
+}
+
+/// Short-lived threads or subprocesses spawned at the `rate` per second, each one burning cpu for
+/// `burn` before it exits
+#[derive(Clone, Copy, Debug)]
+pub struct Churn {
+    pub rate: f64,
+    pub burn: Duration,
+}
+
+impl Churn {
+    /// Cpu load of the churn in percent of one CPU, the threads or subprocesses are gone by the
+    /// time it is measured
+    pub fn target(&self) -> f64 {
+        self.rate * self.burn.as_secs_f64() * 100.0
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct MonitoringTestEnvironment {
-    pub cpu_load: bool,
+    pub scenario: Option<Scenario>,
+
+    pub trace: Option<Trace>,
+
+    pub trace_speed: f64,
+
+    pub trace_start: Option<SystemTime>,
+
+    pub cpu_load: Option<f64>,
+
+    pub cpu_threads: Vec<ThreadLoad>,
+
+    pub idle_threads: Option<usize>,
+
+    pub fork_storm: Option<Churn>,
+
+    pub thread_churn: Option<Churn>,
+
+    pub cpu_profile: LoadProfile,
+
+    pub cpu_profile_start: Option<SystemTime>,
+
+    pub cpu_controller: bool,
 
     pub memory_load: Option<usize>,
 
+    pub memory_strategy: MemoryStrategy,
+
+    pub memory_growth: Option<MemoryGrowth>,
+
+    pub fd_load: Option<usize>,
+
+    pub fd_kinds: Vec<FdKind>,
+
     pub network_and_io_load: Option<u64>,
 
-    pub disk_load: Option<u64>,
+    pub network_source: Option<String>,
+
+    pub p2p_peers: Option<usize>,
+
+    pub p2p_port: u16,
+
+    pub p2p_message_size: u16,
+
+    pub p2p_message_rate: f64,
+
+    pub network_send_load: Option<u64>,
+
+    pub network_sink: Option<String>,
+
+    pub io_write_load: Option<u64>,
+
+    pub io_chunk_size: u64,
+
+    pub io_rotate_size: Option<u64>,
+
+    pub io_read_load: Option<u64>,
+
+    pub io_read_file_size: u64,
+
+    pub io_drop_cache: bool,
+
+    pub disk_load: Option<Vec<u64>>,
+
+    pub disk_allocation: DiskAllocation,
+
+    pub disk_layout: DiskLayout,
+
+    pub disk_files: DiskFiles,
+
+    pub disk_links: Option<DiskLinks>,
+
+    pub disk_growth: Option<DiskGrowth>,
 
     pub test_cpu: Option<f64>,
 
+    pub test_cpu_threads: Vec<ThreadLoad>,
+
+    pub test_cpu_error_margin: f64,
+
+    pub test_threads: Option<usize>,
+
+    pub test_fork_storm: Option<Churn>,
+
+    pub test_subprocess_restart: Option<usize>,
+
+    pub test_thread_churn: Option<Churn>,
+
     pub test_memory: Option<u64>,
 
+    pub test_memory_semantics: MemorySemantics,
+
+    pub test_fd_load: Option<usize>,
+
     pub test_network_and_io: Option<u64>,
 
-    pub test_disk: Option<u64>,
+    pub test_io_write: Option<u64>,
+
+    pub test_network: Option<u64>,
+
+    pub test_io_read: Option<u64>,
+
+    pub test_network_sent: Option<u64>,
+
+    pub test_p2p: Option<usize>,
+
+    pub test_disk: Option<Vec<u64>>,
+
+    pub test_disk_semantics: DiskSizeSemantics,
+
+    pub test_trace: Option<Trace>,
 
     pub disable_rpc_server: bool,
 
+    pub subprocess_restart: Option<RestartSchedule>,
+
     pub cpu_load_with_subprocess: bool,
 
     pub process_name: Option<String>,
@@ -29,45 +214,287 @@ impl MonitoringTestEnvironment {
     pub fn from_args() -> Self {
         let app = monitoring_test_app();
         let args = app.clone().get_matches();
+        let disk_layout: DiskLayout = parsed(&args, "disk-layout", str::parse).unwrap_or_default();
+        let disk_load = parsed(&args, "disk-load", str::parse)
+            .map(|sizes: DiskSizes| or_exit(sizes.resolve(&disk_layout), "disk-load"));
+        let test_disk = parsed(&args, "test-disk", str::parse)
+            .map(|sizes: DiskSizes| or_exit(sizes.resolve(&disk_layout), "test-disk"));
+        let disk_links = args.value_of("disk-links").map(|name| DiskLinks {
+            symlinked: or_exit(disk_layout.find(name), "disk-links").key.clone(),
+            mounted: args
+                .value_of("disk-mount")
+                .map(|name| or_exit(disk_layout.find(name), "disk-mount").key.clone()),
+        });
+        if let Some(links) = &disk_links {
+            or_exit(links.check_layout(&disk_layout), "disk-links");
+        }
+        if disk_links.is_some() && args.value_of("disk-shrink-mode") == Some("delete") {
+            or_exit::<()>(
+                Err("Deleting the files breaks the hard links of --disk-links".to_string()),
+                "disk-shrink-mode",
+            );
+        }
+        let trace = parsed(&args, "trace", str::parse)
+            .inspect(|trace: &Trace| or_exit(trace.check_layout(&disk_layout), "trace"));
+        let test_trace = parsed(&args, "test-trace", str::parse)
+            .inspect(|trace: &Trace| or_exit(trace.check_layout(&disk_layout), "test-trace"));
+        let cpu_threads = all_parsed(&args, "cpu-load-thread", str::parse);
+        or_exit(check_unique_names(&cpu_threads), "cpu-load-thread");
+        let test_cpu_threads = all_parsed(&args, "test-cpu-thread", str::parse);
+        or_exit(check_unique_names(&test_cpu_threads), "test-cpu-thread");
+        let cpu_profile = parsed(&args, "cpu-profile", str::parse).unwrap_or_default();
+        if args.is_present("test-cpu")
+            && cpu_profile != LoadProfile::Constant
+            && !args.is_present("cpu-profile-start")
+        {
+            or_exit::<()>(
+                Err("Needs the --cpu-profile-start printed by the cpu load".to_string()),
+                "cpu-profile",
+            );
+        }
 
         Self {
-            cpu_load: args.is_present("cpu-load"),
+            scenario: parsed(&args, "scenario", str::parse),
+            trace,
+            trace_speed: parsed(&args, "trace-speed", positive_number).unwrap_or(1.0),
+            trace_start: parsed(&args, "trace-start", parse_unix_millis)
+                .map(profile::from_unix_millis),
+            cpu_load: args
+                .is_present("cpu-load")
+                .then(|| parsed(&args, "cpu-load", thread_percent).unwrap_or(100.0)),
+            cpu_threads,
+            idle_threads: parsed(&args, "idle-threads", parse_count),
+            fork_storm: parsed(&args, "fork-storm", positive_frequency)
+                .map(|rate| churn(&args, "fork-storm-cpu", rate)),
+            thread_churn: parsed(&args, "thread-churn", positive_frequency)
+                .map(|rate| churn(&args, "thread-churn-cpu", rate)),
+            cpu_profile,
+            cpu_profile_start: parsed(&args, "cpu-profile-start", parse_unix_millis)
+                .map(profile::from_unix_millis),
+            cpu_controller: args.is_present("cpu-controller"),
             disable_rpc_server: args.is_present("disable-rpc-server"),
+            subprocess_restart: parsed(&args, "subprocess-restart", str::parse),
             cpu_load_with_subprocess: args.is_present("cpu-load-with-subprocess"),
             process_name: args
                 .value_of("process-name")
                 .map(|process_name| process_name.to_string()),
-            memory_load: args.value_of("memory-load").map(|memory_load| {
-                memory_load
-                    .parse::<usize>()
-                    .expect("Was expecting NUM [usize]")
+            memory_load: parsed(&args, "memory-load", memory_size),
+            memory_strategy: parsed(&args, "memory-strategy", str::parse).unwrap_or_default(),
+            memory_growth: parsed(&args, "memory-growth-rate", positive_rate).map(|rate| {
+                MemoryGrowth {
+                    rate: rate as usize,
+                    mode: parsed(&args, "memory-growth-mode", str::parse)
+                        .unwrap_or(GrowthMode::Hold),
+                }
             }),
-            disk_load: args
-                .value_of("disk-load")
-                .map(|disk_load| disk_load.parse::<u64>().expect("Was expecting NUM [u64]")),
-            network_and_io_load: args
-                .value_of("network-and-io-load")
-                .map(|network_and_io_load| {
-                    network_and_io_load
-                        .parse::<u64>()
-                        .expect("Was expecting NUM [u64]")
+            fd_load: parsed(&args, "fd-load", positive_count),
+            fd_kinds: parsed(&args, "fd-kinds", fd::parse_kinds)
+                .unwrap_or_else(|| vec![FdKind::File, FdKind::Pipe, FdKind::Socket]),
+            disk_load,
+            disk_allocation: parsed(&args, "disk-allocation", str::parse).unwrap_or_default(),
+            disk_layout,
+            disk_files: parsed(&args, "disk-files", positive_count)
+                .map(|count| DiskFiles {
+                    count,
+                    per_dir: parsed(&args, "disk-files-per-dir", parse_fanout)
+                        .unwrap_or(DiskFiles::default().per_dir),
+                })
+                .unwrap_or_default(),
+            disk_links,
+            disk_growth: parsed(&args, "disk-growth-rate", positive_rate).map(|rate| DiskGrowth {
+                rate,
+                shrink: parsed(&args, "disk-shrink-mode", str::parse).map(|mode| DiskShrink {
+                    mode,
+                    interval: parsed(&args, "disk-shrink-interval", quantity::positive_duration)
+                        .unwrap_or(Duration::from_secs(60)),
                 }),
-            test_cpu: args
-                .value_of("test-cpu")
-                .map(|target| target.parse::<f64>().expect("Was expecting FLOAT [f64]")),
-            test_memory: args
-                .value_of("test-memory")
-                .map(|target| target.parse::<u64>().expect("Was expecting NUM [u64]")),
-            test_disk: args
-                .value_of("test-disk")
-                .map(|target| target.parse::<u64>().expect("Was expecting NUM [u64]")),
-            test_network_and_io: args
-                .value_of("test-networking-and-io")
-                .map(|target| target.parse::<u64>().expect("Was expecting NUM [u64]")),
+            }),
+            network_and_io_load: parsed(&args, "network-and-io-load", positive_rate),
+            network_source: args
+                .value_of("network-source")
+                .map(|network_source| network_source.to_string()),
+            p2p_peers: parsed(&args, "p2p-load", parse_count),
+            p2p_port: parsed(&args, "p2p-port", parse_port).unwrap_or(9732),
+            p2p_message_size: parsed(&args, "p2p-message-size", parse_message_size).unwrap_or(1024),
+            p2p_message_rate: parsed(&args, "p2p-message-rate", positive_frequency).unwrap_or(10.0),
+            network_send_load: parsed(&args, "network-send-load", positive_rate),
+            network_sink: args
+                .value_of("network-sink")
+                .map(|network_sink| network_sink.to_string()),
+            io_write_load: parsed(&args, "io-write-load", positive_rate),
+            io_chunk_size: parsed(&args, "io-chunk-size", quantity::parse_size).unwrap_or(65_536),
+            io_rotate_size: parsed(&args, "io-rotate-size", quantity::parse_size),
+            io_read_load: parsed(&args, "io-read-load", positive_rate),
+            io_read_file_size: parsed(&args, "io-read-file-size", positive_size)
+                .unwrap_or(67_108_864),
+            io_drop_cache: !args.is_present("io-keep-cache"),
+            test_cpu: parsed(&args, "test-cpu", quantity::parse_percent),
+            test_cpu_threads,
+            test_cpu_error_margin: parsed(&args, "test-cpu-error-margin", positive_percent)
+                .unwrap_or(10.0),
+            test_threads: parsed(&args, "test-threads", parse_count),
+            test_subprocess_restart: parsed(&args, "test-subprocess-restart", positive_count),
+            test_fork_storm: parsed(&args, "test-fork-storm", positive_frequency)
+                .map(|rate| churn(&args, "fork-storm-cpu", rate)),
+            test_thread_churn: parsed(&args, "test-thread-churn", positive_frequency)
+                .map(|rate| churn(&args, "thread-churn-cpu", rate)),
+            test_memory: parsed(&args, "test-memory", quantity::parse_size),
+            test_memory_semantics: parsed(&args, "test-memory-semantics", str::parse)
+                .unwrap_or_default(),
+            test_fd_load: args
+                .is_present("test-fd-load")
+                .then(|| parsed(&args, "test-fd-load", positive_count).unwrap_or(3)),
+            test_disk,
+            test_disk_semantics: parsed(&args, "test-disk-semantics", str::parse)
+                .unwrap_or_default(),
+            test_trace,
+            test_network_and_io: parsed(&args, "test-networking-and-io", quantity::parse_rate),
+            test_io_write: parsed(&args, "test-io-write", quantity::parse_rate),
+            test_network: parsed(&args, "test-network", quantity::parse_rate),
+            test_io_read: parsed(&args, "test-io-read", quantity::parse_rate),
+            test_network_sent: parsed(&args, "test-network-sent", quantity::parse_rate),
+            test_p2p: parsed(&args, "test-p2p", positive_count),
         }
     }
 }
 
+/// Returns a clap validator using `parse`, so an invalid value is reported against its flag
+fn validated_by<T>(parse: fn(&str) -> Result<T, String>) -> impl Fn(String) -> Result<(), String> {
+    move |value| parse(&value).map(|_| ())
+}
+
+/// Parses the value of the argument `name`, the value was already checked by its validator
+fn parsed<T>(args: &ArgMatches, name: &str, parse: fn(&str) -> Result<T, String>) -> Option<T> {
+    args.value_of(name)
+        .map(|value| parse(value).unwrap_or_else(|e| panic!("--{}: {}", name, e)))
+}
+
+/// Parses every value of the repeatable argument `name`
+fn all_parsed<T>(args: &ArgMatches, name: &str, parse: fn(&str) -> Result<T, String>) -> Vec<T> {
+    args.values_of(name)
+        .map(|values| {
+            values
+                .map(|value| parse(value).unwrap_or_else(|e| panic!("--{}: {}", name, e)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The churn at the `rate` burning the cpu time of the argument `burn`, shared by a load and its
+/// test
+fn churn(args: &ArgMatches, burn: &str, rate: f64) -> Churn {
+    Churn {
+        rate,
+        burn: parsed(args, burn, quantity::positive_duration).unwrap_or(Duration::from_millis(10)),
+    }
+}
+
+/// Unwraps a value checked against the disk layout or other values, an error is reported against
+/// the flag `name`
+fn or_exit<T>(result: Result<T, String>, name: &str) -> T {
+    result.unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Invalid value for '--{}': {}", name, e),
+            ErrorKind::ValueValidation,
+        )
+        .exit()
+    })
+}
+
+fn positive_size(value: &str) -> Result<u64, String> {
+    match quantity::parse_size(value)? {
+        0 => Err(format!("Was expecting a positive size, got {}", value)),
+        size => Ok(size),
+    }
+}
+
+fn positive_rate(value: &str) -> Result<u64, String> {
+    match quantity::parse_rate(value)? {
+        0 => Err(format!("Was expecting a positive rate, got {}", value)),
+        rate => Ok(rate),
+    }
+}
+
+/// An allocation is limited to isize::MAX bytes
+pub fn memory_size(value: &str) -> Result<usize, String> {
+    let size = quantity::parse_size(value)?;
+    usize::try_from(size)
+        .ok()
+        .filter(|size| isize::try_from(*size).is_ok())
+        .ok_or_else(|| format!("Memory size {} does not fit the address space", value))
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .map_err(|_| format!("Was expecting a count, got {}", value))
+}
+
+fn positive_count(value: &str) -> Result<usize, String> {
+    match parse_count(value)? {
+        0 => Err(format!("Was expecting a positive count, got {}", value)),
+        count => Ok(count),
+    }
+}
+
+/// A directory holds at least 2 entries, otherwise the files cannot be nested
+fn parse_fanout(value: &str) -> Result<usize, String> {
+    match parse_count(value)? {
+        0 | 1 => Err(format!("Was expecting at least 2 entries, got {}", value)),
+        count => Ok(count),
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16, String> {
+    value
+        .parse::<u16>()
+        .map_err(|_| format!("Was expecting a port, got {}", value))
+}
+
+fn positive_percent(value: &str) -> Result<f64, String> {
+    match quantity::parse_percent(value)? {
+        percent if percent > 0.0 => Ok(percent),
+        _ => Err(format!(
+            "Was expecting a positive percentage, got {}",
+            value
+        )),
+    }
+}
+
+fn positive_number(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| *number > 0.0 && number.is_finite())
+        .ok_or_else(|| format!("Was expecting a positive number, got {}", value))
+}
+
+/// Events per second, the interval between two of them has to be at least 1ns and fit a Duration
+fn positive_frequency(value: &str) -> Result<f64, String> {
+    let rate = positive_number(value)?;
+    match Duration::try_from_secs_f64(1.0 / rate) {
+        Ok(interval) if !interval.is_zero() => Ok(rate),
+        _ => Err(format!(
+            "Was expecting at most 1e9 events per second and at least one every {} seconds, got {}",
+            u64::MAX,
+            value
+        )),
+    }
+}
+
+/// P2p messages are prefixed by their size in 2 bytes
+fn parse_message_size(value: &str) -> Result<u16, String> {
+    let size = quantity::parse_size(value)?;
+    u16::try_from(size)
+        .map_err(|_| format!("Was expecting at most {} bytes, got {}", u16::MAX, value))
+}
+
+fn parse_unix_millis(value: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .map_err(|_| format!("Was expecting unix time in milliseconds, got {}", value))
+}
+
 fn monitoring_test_app() -> App<'static, 'static> {
     let app = App::new("Tezedge node monitoring app")
         .version("1.7.0")
@@ -85,10 +512,126 @@ fn monitoring_test_app() -> App<'static, 'static> {
                 .value_name("STRING")
                 .help("Sets the process' name"),
         )
+        .arg(
+            Arg::with_name("scenario")
+                .long("scenario")
+                .takes_value(true)
+                .value_name("FILE")
+                .validator(validated_by(str::parse::<Scenario>))
+                .help("Launches the app running every load of the provided toml scenario file at the same time, each one in the node or in a subprocess from its start offset for its duration"),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .takes_value(true)
+                .value_name("FILE")
+                .validator(validated_by(str::parse::<Trace>))
+                .help("Launches the app replaying the cpu load of every thread, the memory, the io and the database sizes recorded in a json monitoring export or a csv trace file"),
+        )
+        .arg(
+            Arg::with_name("trace-speed")
+                .long("trace-speed")
+                .takes_value(true)
+                .value_name("FACTOR")
+                .validator(validated_by(positive_number))
+                .help("Replays the trace the provided number of times faster than it was recorded, used by the trace replay and the trace test (defaults to 1)"),
+        )
+        .arg(
+            Arg::with_name("trace-start")
+                .long("trace-start")
+                .takes_value(true)
+                .value_name("UNIX_MS")
+                .validator(validated_by(parse_unix_millis))
+                .help("Start of the trace replay in unix milliseconds, printed by the trace replay and used by the trace test (the replay defaults to now)"),
+        )
         .arg(
             Arg::with_name("cpu-load")
                 .long("cpu-load")
-                .help("Launches the app with 100% cpu load"),
+                .takes_value(true)
+                .min_values(0)
+                .value_name("PERCENT")
+                .validator(validated_by(thread_percent))
+                .help("Launches the app with the provided cpu load in percent of one CPU, at most 100% (defaults to 100%)"),
+        )
+        .arg(
+            Arg::with_name("cpu-load-thread")
+                .long("cpu-load-thread")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME=PERCENT")
+                .requires("cpu-load")
+                .validator(validated_by(str::parse::<ThreadLoad>))
+                .help("Launches a named thread with the provided cpu load in percent, can be repeated (defaults to one test_thread with the --cpu-load target)"),
+        )
+        .arg(
+            Arg::with_name("idle-threads")
+                .long("idle-threads")
+                .takes_value(true)
+                .value_name("COUNT")
+                .validator(validated_by(parse_count))
+                .help("Launches the app keeping the provided number of idle threads named idle-N alive"),
+        )
+        .arg(
+            Arg::with_name("thread-churn")
+                .long("thread-churn")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_frequency))
+                .help("Launches the app spawning the provided number of short-lived threads named churn-N per second, can be combined with --idle-threads"),
+        )
+        .arg(
+            Arg::with_name("thread-churn-cpu")
+                .long("thread-churn-cpu")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validated_by(quantity::positive_duration))
+                .help("Cpu time every churned thread burns before it exits, used by the thread load and the thread test (defaults to 10ms)"),
+        )
+        .arg(
+            Arg::with_name("cpu-profile")
+                .long("cpu-profile")
+                .takes_value(true)
+                .value_name("PROFILE")
+                .validator(validated_by(str::parse::<LoadProfile>))
+                .help("Varies the cpu load over time: constant, ramp:DURATION, step:STEPS:DURATION, sine:DURATION or square:DURATION, used by the cpu load and the cpu test (defaults to constant)"),
+        )
+        .arg(
+            Arg::with_name("cpu-profile-start")
+                .long("cpu-profile-start")
+                .takes_value(true)
+                .value_name("UNIX_MS")
+                .validator(validated_by(parse_unix_millis))
+                .help("Start of the cpu profile in unix milliseconds, printed by the cpu load and used by the cpu test and the subprocesses to stay in phase with the node (the load defaults to now)"),
+        )
+        .arg(
+            Arg::with_name("cpu-controller")
+                .long("cpu-controller")
+                .help("Holds the cpu load of every thread at its target with a feedback controller reading the cpu times of the threads from /proc, instead of a fixed duty cycle, and reports the measured cpu load, used by the cpu load and the cpu loads of a scenario or a trace"),
+        )
+        .arg(
+            Arg::with_name("subprocess-restart")
+                .long("subprocess-restart")
+                .takes_value(true)
+                .value_name("SCHEDULE")
+                .validator(validated_by(str::parse::<RestartSchedule>))
+                .help("Kills and respawns the protocol-runner subprocess of the cpu and memory loads every DURATION or after random:MIN-MAX intervals, e.g. 30s or random:10s-60s"),
+        )
+        .arg(
+            Arg::with_name("fork-storm")
+                .long("fork-storm")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_frequency))
+                .help("Launches the app spawning the provided number of short-lived subprocesses per second, reaping them once they exit"),
+        )
+        .arg(
+            Arg::with_name("fork-storm-cpu")
+                .long("fork-storm-cpu")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validated_by(quantity::positive_duration))
+                .help("Cpu time every subprocess of the fork storm burns before it exits, used by the fork storm load and test (defaults to 10ms)"),
         )
         .arg(
             Arg::with_name("cpu-load-with-subprocess")
@@ -101,50 +644,413 @@ fn monitoring_test_app() -> App<'static, 'static> {
             Arg::with_name("memory-load")
                 .long("memory-load")
                 .takes_value(true)
-                .value_name("NUM")
-                .help("Launches the app with the provided memory load in GB"),
+                .value_name("SIZE")
+                .validator(validated_by(memory_size))
+                .help("Launches the app with the provided memory load, e.g. 2GiB, 512MB or a number of bytes"),
+        )
+        .arg(
+            Arg::with_name("memory-strategy")
+                .long("memory-strategy")
+                .takes_value(true)
+                .value_name("STRATEGY")
+                .possible_values(&["heap", "anonymous-mmap", "file-mmap", "shared-memory"])
+                .help("Allocates the memory load on the heap, with anonymous mmap, file backed mmap or in /dev/shm, used by the memory load and the memory test (defaults to heap)"),
+        )
+        .arg(
+            Arg::with_name("memory-growth-rate")
+                .long("memory-growth-rate")
+                .takes_value(true)
+                .value_name("RATE")
+                .requires("memory-load")
+                .validator(validated_by(positive_rate))
+                .help("Grows the memory by the provided rate, e.g. 10MB/s, up to the memory load instead of allocating it at once"),
+        )
+        .arg(
+            Arg::with_name("memory-growth-mode")
+                .long("memory-growth-mode")
+                .takes_value(true)
+                .value_name("MODE")
+                .possible_values(&["hold", "repeat"])
+                .requires("memory-growth-rate")
+                .help("Holds the memory once the memory load is reached or releases it and grows again (defaults to hold)"),
+        )
+        .arg(
+            Arg::with_name("fd-load")
+                .long("fd-load")
+                .takes_value(true)
+                .value_name("COUNT")
+                .validator(validated_by(positive_count))
+                .help("Launches the app holding the provided number of open file descriptors, or as many as the open files limit allows"),
+        )
+        .arg(
+            Arg::with_name("fd-kinds")
+                .long("fd-kinds")
+                .takes_value(true)
+                .value_name("KINDS")
+                .requires("fd-load")
+                .validator(validated_by(fd::parse_kinds))
+                .help("Kinds of the file descriptors opened in turn, files, pipes and sockets separated by commas (defaults to files,pipes,sockets)"),
         )
         .arg(
             Arg::with_name("disk-load")
                 .long("disk-load")
                 .takes_value(true)
-                .value_name("NUM")
-                .help("Launches the app with the provided disk load in GB"),
+                .value_name("SIZES")
+                .validator(validated_by(str::parse::<DiskSizes>))
+                .help("Launches the app with the provided disk load, a size for every database or NAME=SIZE pairs separated by commas, e.g. 2GiB or context=3GiB,block_storage=1GiB,1GiB"),
+        )
+        .arg(
+            Arg::with_name("disk-allocation")
+                .long("disk-allocation")
+                .takes_value(true)
+                .value_name("ALLOCATION")
+                .possible_values(&["sparse", "fallocate", "write"])
+                .help("Creates the database files sparse with set_len, with fallocate or by writing them out, used by the disk load and the disk test (defaults to sparse)"),
+        )
+        .arg(
+            Arg::with_name("disk-layout")
+                .long("disk-layout")
+                .takes_value(true)
+                .value_name("LAYOUT")
+                .validator(validated_by(str::parse::<DiskLayout>))
+                .help("Database directories created by the disk load and asserted by the disk test: tezedge, octez or a json file with a list of {\"path\": ..., \"key\": ...} objects (defaults to tezedge)"),
+        )
+        .arg(
+            Arg::with_name("disk-files")
+                .long("disk-files")
+                .takes_value(true)
+                .value_name("COUNT")
+                .validator(validated_by(positive_count))
+                .help("Spreads the size of every database over the provided number of files in nested subdirectories, used by the disk load and the disk test (defaults to a single file)"),
+        )
+        .arg(
+            Arg::with_name("disk-files-per-dir")
+                .long("disk-files-per-dir")
+                .takes_value(true)
+                .value_name("COUNT")
+                .requires("disk-files")
+                .validator(validated_by(parse_fanout))
+                .help("Maximum number of entries in a database directory before the files are nested in subdirectories (defaults to 100)"),
+        )
+        .arg(
+            Arg::with_name("disk-links")
+                .long("disk-links")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Symlinks the directory of the NAME database onto a directory outside of the databases, and adds a hard link and a symlink to a file outside of the databases to every database, used by the disk load and the disk test"),
+        )
+        .arg(
+            Arg::with_name("disk-mount")
+                .long("disk-mount")
+                .takes_value(true)
+                .value_name("NAME")
+                .requires("disk-links")
+                .help("Puts the files of the NAME database onto a tmpfs mounted into the database directory, needs CAP_SYS_ADMIN, used by the disk load and the disk test"),
+        )
+        .arg(
+            Arg::with_name("disk-growth-rate")
+                .long("disk-growth-rate")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_rate))
+                .help("Appends to every database file at the provided rate, e.g. 1MB/s, after creating it with the disk load, the disk test then follows the growth instead of the initial sizes"),
+        )
+        .arg(
+            Arg::with_name("disk-shrink-mode")
+                .long("disk-shrink-mode")
+                .takes_value(true)
+                .value_name("MODE")
+                .possible_values(&["truncate", "delete"])
+                .requires("disk-growth-rate")
+                .help("Truncates the database files back to the disk load or deletes them on the shrink interval, used by the disk load and the disk test"),
+        )
+        .arg(
+            Arg::with_name("disk-shrink-interval")
+                .long("disk-shrink-interval")
+                .takes_value(true)
+                .value_name("DURATION")
+                .requires("disk-shrink-mode")
+                .validator(validated_by(quantity::positive_duration))
+                .help("Interval between two shrinks of the database files, e.g. 5m (defaults to 60s)"),
         )
         .arg(
             Arg::with_name("network-and-io-load")
                 .long("network-and-io-load")
                 .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_rate))
+                .help("Launches the app with the newtwork and io load, e.g. 300KiB/s or a number of bytes per second"),
+        )
+        .arg(
+            Arg::with_name("network-source")
+                .long("network-source")
+                .takes_value(true)
+                .value_name("URL")
+                .requires("network-and-io-load")
+                .help("Downloads the network load from the provided url instead of the built-in local data source, which runs in the node and sends every byte the node receives"),
+        )
+        .arg(
+            Arg::with_name("p2p-load")
+                .long("p2p-load")
+                .takes_value(true)
+                .value_name("PEERS")
+                .validator(validated_by(parse_count))
+                .help("Launches the app with a p2p listener and the provided number of simulated peers exchanging messages with it"),
+        )
+        .arg(
+            Arg::with_name("p2p-port")
+                .long("p2p-port")
+                .takes_value(true)
+                .value_name("PORT")
+                .requires("p2p-load")
+                .validator(validated_by(parse_port))
+                .help("Port of the p2p listener (defaults to 9732)"),
+        )
+        .arg(
+            Arg::with_name("p2p-message-size")
+                .long("p2p-message-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .validator(validated_by(parse_message_size))
+                .help("Size of the p2p message payload, at most 64KiB - 1, used by the p2p load and the p2p test (defaults to 1KiB)"),
+        )
+        .arg(
+            Arg::with_name("p2p-message-rate")
+                .long("p2p-message-rate")
+                .takes_value(true)
                 .value_name("NUM")
-                .help("Launches the app with the newtwork and io load"),
+                .validator(validated_by(positive_frequency))
+                .help("Messages sent per second by every simulated peer, used by the p2p load and the p2p test (defaults to 10)"),
+        )
+        .arg(
+            Arg::with_name("network-send-load")
+                .long("network-send-load")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_rate))
+                .help("Launches the app sending data at the provided rate, e.g. 1MiB/s"),
+        )
+        .arg(
+            Arg::with_name("network-sink")
+                .long("network-sink")
+                .takes_value(true)
+                .value_name("HOST:PORT")
+                .requires("network-send-load")
+                .help("Sends the network send load to the provided address instead of the built-in local data sink, which runs in the node and receives every byte the node sends"),
+        )
+        .arg(
+            Arg::with_name("io-write-load")
+                .long("io-write-load")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_rate))
+                .help("Launches the app writing to the volume at the provided rate, e.g. 1MiB/s"),
+        )
+        .arg(
+            Arg::with_name("io-chunk-size")
+                .long("io-chunk-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .requires("io-loads")
+                .validator(validated_by(positive_size))
+                .help("Size of a single write or read of the io loads (defaults to 64KiB)"),
+        )
+        .arg(
+            Arg::with_name("io-rotate-size")
+                .long("io-rotate-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .requires("io-write-load")
+                .validator(validated_by(positive_size))
+                .help("Removes the written file and starts a new one once it reaches the provided size (defaults to never)"),
+        )
+        .arg(
+            Arg::with_name("io-read-load")
+                .long("io-read-load")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_rate))
+                .help("Launches the app re-reading a prepared file in the volume at the provided rate, e.g. 1MiB/s"),
+        )
+        .arg(
+            Arg::with_name("io-read-file-size")
+                .long("io-read-file-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .requires("io-read-load")
+                .validator(validated_by(positive_size))
+                .help("Size of the file read by the io read load (defaults to 64MiB)"),
+        )
+        .arg(
+            Arg::with_name("io-keep-cache")
+                .long("io-keep-cache")
+                .requires("io-read-load")
+                .help("Keeps the read file in the page cache, the io read load then barely hits the storage (defaults to dropping every read chunk from the page cache)"),
         )
         .arg(
             Arg::with_name("test-cpu")
                 .long("test-cpu")
                 .takes_value(true)
-                .value_name("FLOAT")
-                .help("Launches test to assert cpu measurement"),
+                .value_name("PERCENT")
+                .validator(validated_by(quantity::parse_percent))
+                .help("Launches test to assert cpu measurement, e.g. 75%"),
+        )
+        .arg(
+            Arg::with_name("test-cpu-thread")
+                .long("test-cpu-thread")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME=PERCENT")
+                .requires("test-cpu")
+                .validator(validated_by(str::parse::<ThreadLoad>))
+                .help("Asserts the cpu measurement of a named thread, can be repeated (defaults to test_thread with the --test-cpu target)"),
+        )
+        .arg(
+            Arg::with_name("test-cpu-error-margin")
+                .long("test-cpu-error-margin")
+                .takes_value(true)
+                .value_name("PERCENT")
+                .requires("test-cpu")
+                .validator(validated_by(positive_percent))
+                .help("Error margin of the cpu measurement of every thread, a load run with --cpu-controller holds its target closely enough for a few percent (defaults to 10%)"),
+        )
+        .arg(
+            Arg::with_name("test-threads")
+                .long("test-threads")
+                .takes_value(true)
+                .value_name("COUNT")
+                .validator(validated_by(parse_count))
+                .help("Launches test to assert the node thread measurements hold the provided number of idle threads"),
+        )
+        .arg(
+            Arg::with_name("test-thread-churn")
+                .long("test-thread-churn")
+                .takes_value(true)
+                .value_name("RATE")
+                .requires("test-threads")
+                .validator(validated_by(positive_frequency))
+                .help("Asserts the thread measurements stay bounded and the cpu of the exited threads is counted under the provided thread churn rate"),
+        )
+        .arg(
+            Arg::with_name("test-fork-storm")
+                .long("test-fork-storm")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(positive_frequency))
+                .help("Launches test to assert the node cpu measurement counts the cpu of the subprocesses exiting between two samples under the provided fork storm rate"),
+        )
+        .arg(
+            Arg::with_name("test-subprocess-restart")
+                .long("test-subprocess-restart")
+                .takes_value(true)
+                .value_name("SAMPLES")
+                .validator(validated_by(positive_count))
+                .help("Launches test to assert the cpu and memory subprocess measurements drop a restarted protocol-runner and pick up its new pid within the provided number of samples"),
         )
         .arg(
             Arg::with_name("test-memory")
                 .long("test-memory")
                 .takes_value(true)
-                .value_name("NUM")
+                .value_name("SIZE")
+                .validator(validated_by(quantity::parse_size))
                 .help("Launches test to assert memory measurement"),
         )
+        .arg(
+            Arg::with_name("test-memory-semantics")
+                .long("test-memory-semantics")
+                .takes_value(true)
+                .value_name("SEMANTICS")
+                .possible_values(&["resident", "anonymous"])
+                .requires("test-memory")
+                .help("Memory the monitoring is expected to report, every resident page or the anonymous ones only, which leaves out a file-mmap or shared-memory load (defaults to resident)"),
+        )
+        .arg(
+            Arg::with_name("test-fd-load")
+                .long("test-fd-load")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("SAMPLES")
+                .validator(validated_by(positive_count))
+                .help("Launches test to assert the monitoring keeps measuring the node and the protocol-runner under the fd load for the provided number of fresh measurements (defaults to 3)"),
+        )
         .arg(
             Arg::with_name("test-disk")
                 .long("test-disk")
                 .takes_value(true)
-                .value_name("NUM")
-                .help("Launches test to assert diks measurement"),
+                .value_name("SIZES")
+                .validator(validated_by(str::parse::<DiskSizes>))
+                .help("Launches test to assert diks measurement, a size for every database or NAME=SIZE pairs separated by commas"),
+        )
+        .arg(
+            Arg::with_name("test-disk-semantics")
+                .long("test-disk-semantics")
+                .takes_value(true)
+                .value_name("SEMANTICS")
+                .possible_values(&["apparent", "allocated"])
+                .help("Size the monitoring is expected to report, the apparent file size or the blocks allocated on disk, used by the disk test and the trace test (defaults to apparent)"),
+        )
+        .arg(
+            Arg::with_name("test-trace")
+                .long("test-trace")
+                .takes_value(true)
+                .value_name("FILE")
+                .requires("trace-start")
+                .validator(validated_by(str::parse::<Trace>))
+                .help("Launches test to assert every measurement sampled during the trace replay against the trace, once the replay ended"),
         )
         .arg(
             Arg::with_name("test-networking-and-io")
                 .long("test-networking-and-io")
                 .takes_value(true)
-                .value_name("NUM")
-                .help("Launches test to assert networking and io measurement"),
+                .value_name("RATE")
+                .validator(validated_by(quantity::parse_rate))
+                .help("Launches test to assert networking and io measurement, the received bytes only differ from the sent ones with an external --network-source"),
+        )
+        .arg(
+            Arg::with_name("test-io-write")
+                .long("test-io-write")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(quantity::parse_rate))
+                .help("Launches test to assert io write measurement"),
+        )
+        .arg(
+            Arg::with_name("test-network")
+                .long("test-network")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(quantity::parse_rate))
+                .help("Launches test to assert network received measurement, the received bytes only differ from the sent ones with an external --network-source"),
+        )
+        .arg(
+            Arg::with_name("test-io-read")
+                .long("test-io-read")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(quantity::parse_rate))
+                .help("Launches test to assert io read measurement"),
+        )
+        .arg(
+            Arg::with_name("test-network-sent")
+                .long("test-network-sent")
+                .takes_value(true)
+                .value_name("RATE")
+                .validator(validated_by(quantity::parse_rate))
+                .help("Launches test to assert network sent measurement, the sent bytes only differ from the received ones with an external --network-sink"),
+        )
+        .arg(
+            Arg::with_name("test-p2p")
+                .long("test-p2p")
+                .takes_value(true)
+                .value_name("PEERS")
+                .validator(validated_by(positive_count))
+                .help("Launches test to assert the network measurements of a p2p load with the provided number of peers, the node both sends and receives every message and its echo over loopback"),
+        )
+        .group(
+            ArgGroup::with_name("io-loads")
+                .args(&["io-write-load", "io-read-load"])
+                .multiple(true),
         );
     app
 }
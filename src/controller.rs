@@ -0,0 +1,140 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use tokio::time::interval;
+
+/// Interval over which the controller compares the cpu time used to the target
+const CONTROL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fraction of the error of an interval corrected in the next one, below 1 to smooth out the
+/// clock tick resolution of the cpu times
+const CONTROL_GAIN: f64 = 0.5;
+
+/// Interval between two reports of the cpu times measured by the node
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keeps the cpu load of the calling thread at its target by correcting the busy fraction of the
+/// duty cycle with the error measured in `/proc/self/task/TID/stat`, so the load holds the target
+/// whatever the contention on the host
+pub struct CpuController {
+    /// Added to the target to get the busy fraction, the integral of the past errors
+    correction: f64,
+    /// Cpu time the thread is expected to use since the interval start
+    expected: Duration,
+    cpu_time: Duration,
+    interval_start: Instant,
+    last_cycle: Instant,
+}
+
+impl CpuController {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            correction: 0.0,
+            expected: Duration::ZERO,
+            cpu_time: thread_cpu_time(),
+            interval_start: now,
+            last_cycle: now,
+        }
+    }
+
+    /// Returns the busy fraction of the next duty cycle holding the `target` fraction of one CPU
+    pub fn busy(&mut self, target: f64) -> f64 {
+        let now = Instant::now();
+        // the target of the cycle that just ended, so a varying target is followed as well
+        self.expected += (now - self.last_cycle).mul_f64(target);
+        self.last_cycle = now;
+
+        let elapsed = now - self.interval_start;
+        if elapsed >= CONTROL_INTERVAL {
+            let cpu_time = thread_cpu_time();
+            let used = cpu_time.saturating_sub(self.cpu_time).as_secs_f64();
+            let error = (self.expected.as_secs_f64() - used) / elapsed.as_secs_f64();
+            self.correction = (self.correction + CONTROL_GAIN * error).clamp(-1.0, 1.0);
+
+            self.cpu_time = cpu_time;
+            self.expected = Duration::ZERO;
+            self.interval_start = now;
+        }
+
+        (target + self.correction).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for CpuController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reports the cpu load of the node and of every thread of it, measured from
+/// `/proc/self/stat` and `/proc/self/task/*/stat`, the ground truth of the monitoring
+pub async fn report_cpu_load() {
+    let mut ticks = interval(REPORT_INTERVAL);
+    let mut last = (Instant::now(), process_cpu_time(), task_cpu_times());
+    loop {
+        ticks.tick().await;
+        let now = (Instant::now(), process_cpu_time(), task_cpu_times());
+        let elapsed = (now.0 - last.0).as_secs_f64();
+        let percent = |used: Duration, since: Duration| {
+            used.saturating_sub(since).as_secs_f64() / elapsed * 100.0
+        };
+
+        let threads: Vec<String> = now
+            .2
+            .iter()
+            .filter_map(|(tid, name, cpu_time)| {
+                let (_, _, since) = last.2.iter().find(|(last_tid, _, _)| last_tid == tid)?;
+                Some(format!("{} {:.1}%", name, percent(*cpu_time, *since)))
+            })
+            .collect();
+        println!(
+            "\tMEASURED CPU: {:.1}% ({})",
+            percent(now.1, last.1),
+            threads.join(", ")
+        );
+        last = now;
+    }
+}
+
+/// Returns the cpu time used by this process, its exited threads included
+pub fn process_cpu_time() -> Duration {
+    read_cpu_time("/proc/self/stat").expect("Cannot read /proc/self/stat")
+}
+
+/// Returns the cpu time used by the calling thread
+pub fn thread_cpu_time() -> Duration {
+    // SAFETY: gettid has no arguments and cannot fail
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) };
+    let path = format!("/proc/self/task/{}/stat", tid);
+    read_cpu_time(&path).unwrap_or_else(|| panic!("Cannot read {}", path))
+}
+
+/// Returns the id, the name and the cpu time of every live thread of this process
+pub fn task_cpu_times() -> Vec<(u64, String, Duration)> {
+    let tasks = fs::read_dir("/proc/self/task").expect("Cannot read /proc/self/task");
+    tasks
+        .filter_map(|task| {
+            let task = task.ok()?;
+            let tid = task.file_name().to_str()?.parse().ok()?;
+            // the thread may exit while it is read
+            let name = fs::read_to_string(task.path().join("comm")).ok()?;
+            let cpu_time = read_cpu_time(task.path().join("stat").to_str()?)?;
+            Some((tid, name.trim_end().to_string(), cpu_time))
+        })
+        .collect()
+}
+
+/// Reads the user and system time of a `stat` file of procfs
+fn read_cpu_time(path: &str) -> Option<Duration> {
+    let stat = fs::read_to_string(path).ok()?;
+    // the name in parentheses may contain spaces, the fields after it start with the state
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    // SAFETY: sysconf only reads a system constant
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+    Some(Duration::from_secs_f64(
+        (utime + stime) as f64 / ticks_per_second,
+    ))
+}
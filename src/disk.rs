@@ -0,0 +1,615 @@
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::symlink;
+use std::os::unix::io::AsRawFd;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::quantity;
+
+/// Size of a single write when the dummy database files are written out
+const WRITE_CHUNK_SIZE: u64 = 1_048_576;
+
+/// How the dummy database files get their size, the apparent size is the same for all of them but
+/// the blocks allocated on disk (as reported by `du`) are not
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DiskAllocation {
+    /// `set_len` only, the file is sparse and has (almost) no blocks allocated
+    #[default]
+    Sparse,
+    /// `fallocate`, the blocks are reserved without writing them
+    Fallocate,
+    /// The whole file is written, the blocks are allocated and hold data
+    Write,
+}
+
+impl FromStr for DiskAllocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sparse" => Ok(DiskAllocation::Sparse),
+            "fallocate" => Ok(DiskAllocation::Fallocate),
+            "write" => Ok(DiskAllocation::Write),
+            _ => Err(format!(
+                "Was expecting sparse, fallocate or write, got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DiskAllocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskAllocation::Sparse => write!(f, "sparse"),
+            DiskAllocation::Fallocate => write!(f, "fallocate"),
+            DiskAllocation::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Which size the monitoring is expected to report for a database directory
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DiskSizeSemantics {
+    /// Sum of the apparent file sizes (`du --apparent-size`)
+    #[default]
+    Apparent,
+    /// Sum of the blocks allocated on disk (`du`)
+    Allocated,
+}
+
+impl DiskSizeSemantics {
+    /// Returns the size expected for a file with the apparent size `size`, created with `allocation`
+    pub fn expected_size(&self, size: u64, allocation: DiskAllocation) -> u64 {
+        match (self, allocation) {
+            (DiskSizeSemantics::Allocated, DiskAllocation::Sparse) => 0,
+            _ => size,
+        }
+    }
+}
+
+impl FromStr for DiskSizeSemantics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "apparent" => Ok(DiskSizeSemantics::Apparent),
+            "allocated" => Ok(DiskSizeSemantics::Allocated),
+            _ => Err(format!("Was expecting apparent or allocated, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DiskSizeSemantics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskSizeSemantics::Apparent => write!(f, "apparent"),
+            DiskSizeSemantics::Allocated => write!(f, "allocated"),
+        }
+    }
+}
+
+/// Creates a dummy database file of `size` bytes using the `allocation`
+pub fn create_dummy_file(path: &Path, size: u64, allocation: DiskAllocation) {
+    let mut file =
+        File::create(path).unwrap_or_else(|_| panic!("Failed to create file: {:?}", path));
+    extend_file(&mut file, path, 0, size, allocation);
+}
+
+/// Appends `len` bytes to the dummy database file at `path` using the `allocation`, the file is
+/// created when it was deleted
+pub fn grow_dummy_file(path: &Path, len: u64, allocation: DiskAllocation) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap_or_else(|_| panic!("Failed to open file: {:?}", path));
+    let size = file
+        .metadata()
+        .unwrap_or_else(|_| panic!("Failed to read file metadata: {:?}", path))
+        .len();
+    extend_file(&mut file, path, size, len, allocation);
+}
+
+/// Extends the `file` of `size` bytes by `len` bytes, writes go to the end of the file
+fn extend_file(file: &mut File, path: &Path, size: u64, len: u64, allocation: DiskAllocation) {
+    match allocation {
+        DiskAllocation::Sparse => file
+            .set_len(size + len)
+            .unwrap_or_else(|_| panic!("Failed to set file length: {:?}", path)),
+        DiskAllocation::Fallocate => {
+            if len > 0 {
+                // SAFETY: the file descriptor is owned by `file` and stays open for the call
+                let res = unsafe {
+                    libc::fallocate(file.as_raw_fd(), 0, size as libc::off_t, len as libc::off_t)
+                };
+                if res != 0 {
+                    panic!("Failed to fallocate file: {:?}", path);
+                }
+            }
+        }
+        DiskAllocation::Write => {
+            let chunk = vec![0xA5u8; WRITE_CHUNK_SIZE.min(len) as usize];
+            let mut written = 0;
+            while written < len {
+                let chunk_len = WRITE_CHUNK_SIZE.min(len - written);
+                file.write_all(&chunk[..chunk_len as usize])
+                    .unwrap_or_else(|_| panic!("Failed to write file: {:?}", path));
+                written += chunk_len;
+            }
+            file.sync_all()
+                .unwrap_or_else(|_| panic!("Failed to sync file: {:?}", path));
+        }
+    }
+}
+
+/// How the size of a database is spread over its files, RocksDB and sled like stores hold
+/// thousands of SST or segment files
+#[derive(Clone, Copy, Debug)]
+pub struct DiskFiles {
+    /// Number of files of every database
+    pub count: usize,
+    /// Maximum number of entries in a directory, the files are nested in subdirectories beyond it
+    pub per_dir: usize,
+}
+
+impl DiskFiles {
+    /// Creates the files of a database of `size` bytes in `dir`, returns the last file with its size
+    pub fn create(&self, dir: &Path, size: u64, allocation: DiskAllocation) -> (PathBuf, u64) {
+        let mut last = (dir.join(self.path(0)), 0);
+        for index in 0..self.count {
+            let file_size = self.file_size(size, index);
+            let path = dir.join(self.path(index));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent));
+            }
+            create_dummy_file(&path, file_size, allocation);
+            last = (path, file_size);
+        }
+        last
+    }
+
+    /// Size of the `index`th file of a database of `size` bytes, the remainder of the split goes
+    /// to the first files
+    pub fn file_size(&self, size: u64, index: usize) -> u64 {
+        let count = self.count as u64;
+        size / count + u64::from((index as u64) < size % count)
+    }
+
+    /// Path of the `index`th file relative to the database directory, a single file is `dummy.db`
+    pub fn path(&self, index: usize) -> PathBuf {
+        if self.count == 1 {
+            return PathBuf::from("dummy.db");
+        }
+
+        let mut levels = 1;
+        let mut capacity = self.per_dir;
+        while capacity < self.count {
+            capacity = capacity.saturating_mul(self.per_dir);
+            levels += 1;
+        }
+
+        let mut path = PathBuf::new();
+        for level in (1..levels).rev() {
+            path.push(format!(
+                "{:03}",
+                index / self.per_dir.pow(level) % self.per_dir
+            ));
+        }
+        path.push(format!("{:06}.db", index));
+        path
+    }
+}
+
+impl Default for DiskFiles {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            per_dir: 100,
+        }
+    }
+}
+
+/// Directory of the volume holding everything the links point to, no database may cover it, so
+/// it is shared with the monitoring like the databases but outside of all of them
+const OUTSIDE_DIR: &str = "links-outside";
+
+/// Directory of the mounted database its files are put into, a mount point nested in the database
+const MOUNT_DIR: &str = "mounted";
+
+/// Links put into the volume by the disk load, the monitoring is expected to count
+///
+/// * a symlinked database directory fully, like a context moved onto a separate drive
+/// * a database directory with a nested mount fully, like a store on a separate drive mounted
+///   into it
+/// * a hardlinked file once, like `du` does
+/// * nothing behind a symlink to a file outside of the databases, the link itself is not followed
+#[derive(Clone, Debug)]
+pub struct DiskLinks {
+    /// Key of the database whose directory is a symlink to a directory outside of the databases
+    pub symlinked: String,
+    /// Key of the database whose files are on a tmpfs mounted into the database directory, it is
+    /// a file system of its own like a separate drive
+    pub mounted: Option<String>,
+}
+
+impl DiskLinks {
+    /// Returns the directory of the volume holding everything the links point to
+    pub fn outside_databases(volume_path: &Path) -> PathBuf {
+        volume_path.join(OUTSIDE_DIR)
+    }
+
+    /// Checks that no database of the `layout` covers the directory the links point to, and that
+    /// the mounted database is not the symlinked one
+    pub fn check_layout(&self, layout: &DiskLayout) -> Result<(), String> {
+        let outside = Path::new(OUTSIDE_DIR);
+        if let Some(database) = layout.databases.iter().find(|database| {
+            database.path.starts_with(outside) || outside.starts_with(&database.path)
+        }) {
+            return Err(format!(
+                "Database {} at {:?} covers {:?}, the directory the links point to",
+                database.key, database.path, outside
+            ));
+        }
+        if self.mounted.as_ref() == Some(&self.symlinked) {
+            return Err(format!(
+                "Database {} cannot be both symlinked and mounted",
+                self.symlinked
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates the directory of the `database` in the volume and returns the directory its files
+    /// go into, the symlinked database is created outside of the databases and linked into the
+    /// volume, the files of the mounted one go onto a tmpfs mounted into it
+    pub fn create_dir(&self, database: &Database, volume_path: &Path) -> PathBuf {
+        let database_path = volume_path.join(&database.path);
+        if database.key == self.symlinked {
+            let target = Self::outside_databases(volume_path).join(&database.path);
+            fs::create_dir_all(&target)
+                .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &target));
+            if let Some(parent) = database_path.parent() {
+                fs::create_dir_all(parent)
+                    .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent));
+            }
+            symlink(&target, &database_path)
+                .unwrap_or_else(|_| panic!("Failed to create symlink: {:?}", &database_path));
+            return database_path;
+        }
+
+        fs::create_dir_all(&database_path)
+            .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &database_path));
+        if self.mounted.as_ref() != Some(&database.key) {
+            return database_path;
+        }
+
+        let mount_point = database_path.join(MOUNT_DIR);
+        fs::create_dir_all(&mount_point)
+            .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &mount_point));
+        mount_tmpfs(&mount_point);
+        mount_point
+    }
+
+    /// Unmounts the tmpfs mounted into the database of the `layout` in the volume, if any, so
+    /// the volume can be removed
+    pub fn unmount(&self, layout: &DiskLayout, volume_path: &Path) {
+        let mounted = self
+            .mounted
+            .as_ref()
+            .and_then(|name| layout.find(name).ok());
+        if let Some(database) = mounted {
+            let mount_point = volume_path.join(&database.path).join(MOUNT_DIR);
+            let path = path_to_cstring(&mount_point);
+            // SAFETY: the path is a valid nul terminated string, a failure only means it was not
+            // mounted by a previous run
+            unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) };
+        }
+    }
+
+    /// Adds a hard link to the first file of the `database` in its `files_dir` and a symlink to a
+    /// file of twice its `size` outside of the databases, neither of them changes the size the
+    /// monitoring should report, the sizes differ so a miscount tells which link was counted
+    pub fn link_files(
+        &self,
+        database: &Database,
+        files_dir: &Path,
+        volume_path: &Path,
+        size: u64,
+        files: DiskFiles,
+        allocation: DiskAllocation,
+    ) {
+        // a hard link cannot cross the mount of the mounted database, it stays next to the files
+        let hard_link = files_dir.join("hardlink.db");
+        fs::hard_link(files_dir.join(files.path(0)), &hard_link)
+            .unwrap_or_else(|_| panic!("Failed to create hard link: {:?}", &hard_link));
+
+        let outside_file =
+            Self::outside_databases(volume_path).join(format!("{}.db", database.key));
+        create_dummy_file(&outside_file, 2 * size, allocation);
+        let outside_link = files_dir.join("outside.db");
+        symlink(&outside_file, &outside_link)
+            .unwrap_or_else(|_| panic!("Failed to create symlink: {:?}", &outside_link));
+    }
+}
+
+/// Mounts a tmpfs onto the `target` directory, the monitoring only sees the mount when the
+/// volume is shared with mount propagation
+fn mount_tmpfs(target: &Path) {
+    let target_path = path_to_cstring(target);
+    // SAFETY: every argument is a valid nul terminated string
+    let res = unsafe {
+        libc::mount(
+            b"tmpfs\0".as_ptr().cast(),
+            target_path.as_ptr(),
+            b"tmpfs\0".as_ptr().cast(),
+            0,
+            b"mode=0755\0".as_ptr().cast(),
+        )
+    };
+    if res != 0 {
+        match io::Error::last_os_error().raw_os_error() {
+            Some(libc::EPERM) => panic!(
+                "Failed to mount a tmpfs onto {:?}: the nested mount needs CAP_SYS_ADMIN",
+                target
+            ),
+            _ => panic!("Failed to mount a tmpfs onto {:?}", target),
+        }
+    }
+}
+
+fn path_to_cstring(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes())
+        .unwrap_or_else(|_| panic!("Path contains a nul byte: {:?}", path))
+}
+
+/// What happens to the dummy database files on the shrink schedule
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiskShrinkMode {
+    /// The files are truncated back to their initial size, like a pruned database
+    Truncate,
+    /// The files are deleted, the growth starts over from an empty file
+    Delete,
+}
+
+impl FromStr for DiskShrinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(DiskShrinkMode::Truncate),
+            "delete" => Ok(DiskShrinkMode::Delete),
+            _ => Err(format!("Was expecting truncate or delete, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DiskShrinkMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskShrinkMode::Truncate => write!(f, "truncate"),
+            DiskShrinkMode::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DiskShrink {
+    pub mode: DiskShrinkMode,
+    pub interval: Duration,
+}
+
+/// Steady growth of every dummy database file, simulating a syncing node, optionally shrunk on a
+/// schedule like a pruning or garbage collecting node
+#[derive(Clone, Copy, Debug)]
+pub struct DiskGrowth {
+    /// Growth rate of every database in bytes per second
+    pub rate: u64,
+    pub shrink: Option<DiskShrink>,
+}
+
+/// A database directory in the volume and the key the monitoring reports its size under
+#[derive(Clone, Debug, Deserialize)]
+pub struct Database {
+    /// Path of the directory, relative to the volume
+    pub path: PathBuf,
+    /// Key of the database in the `disk` section of the monitoring measurements
+    pub key: String,
+}
+
+impl Database {
+    fn is_named(&self, name: &str) -> bool {
+        self.key == name
+            || self.path == Path::new(name)
+            || self.path.file_name() == Some(name.as_ref())
+    }
+}
+
+/// The database directories of a node, shared by the disk load and the disk test
+#[derive(Clone, Debug)]
+pub struct DiskLayout {
+    pub name: String,
+    pub databases: Vec<Database>,
+}
+
+impl DiskLayout {
+    /// The layout of a tezedge node
+    pub fn tezedge() -> Self {
+        Self::preset(
+            "tezedge",
+            &[
+                ("context-stats-db", "contextStats"),
+                ("context", "contextStorage"),
+                ("bootstrap_db/block_storage", "blockStorage"),
+                ("bootstrap_db/db", "mainDb"),
+            ],
+        )
+    }
+
+    /// The layout of an octez node, the block store and the irmin context
+    pub fn octez() -> Self {
+        Self::preset(
+            "octez",
+            &[("store", "blockStorage"), ("context", "contextStorage")],
+        )
+    }
+
+    /// Returns the database named `name`, by its key, its path or the last component of its path
+    pub fn find(&self, name: &str) -> Result<&Database, String> {
+        self.databases
+            .iter()
+            .find(|database| database.is_named(name))
+            .ok_or_else(|| format!("No database named {} in the {} layout", name, self.name))
+    }
+
+    fn preset(name: &str, databases: &[(&str, &str)]) -> Self {
+        Self {
+            name: name.to_string(),
+            databases: databases
+                .iter()
+                .map(|(path, key)| Database {
+                    path: PathBuf::from(path),
+                    key: key.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads a custom layout from a json file, a list of `{"path": "...", "key": "..."}` objects
+    fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read disk layout file {}: {}", path, e))?;
+        let databases: Vec<Database> = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid disk layout file {}: {}", path, e))?;
+
+        if databases.is_empty() {
+            return Err(format!("Disk layout file {} has no databases", path));
+        }
+        for database in &databases {
+            // the volume is removed and recreated by the disk load, stay inside of it
+            let inside_volume = database
+                .path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if !inside_volume {
+                return Err(format!(
+                    "Database path {:?} in {} must be relative to the volume",
+                    database.path, path
+                ));
+            }
+        }
+        for (i, database) in databases.iter().enumerate() {
+            for other in &databases[i + 1..] {
+                if database.key == other.key {
+                    return Err(format!(
+                        "Database key {} in {} is used more than once",
+                        database.key, path
+                    ));
+                }
+                // a database is counted with all its files, one inside another is counted twice
+                let nested = database.path.starts_with(&other.path)
+                    || other.path.starts_with(&database.path);
+                if nested {
+                    return Err(format!(
+                        "Database paths {:?} and {:?} in {} overlap",
+                        database.path, other.path, path
+                    ));
+                }
+            }
+        }
+
+        Ok(Self {
+            name: path.to_string(),
+            databases,
+        })
+    }
+}
+
+impl Default for DiskLayout {
+    fn default() -> Self {
+        Self::tezedge()
+    }
+}
+
+impl FromStr for DiskLayout {
+    type Err = String;
+
+    /// Parses the `tezedge` or `octez` preset, or a path to a custom layout file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tezedge" => Ok(Self::tezedge()),
+            "octez" => Ok(Self::octez()),
+            path => Self::from_file(path),
+        }
+    }
+}
+
+/// Target sizes of the databases, `SIZE` for all of them or `NAME=SIZE` pairs separated by commas,
+/// a database is named by its key, its path or the last component of its path
+#[derive(Clone, Debug)]
+pub struct DiskSizes {
+    /// Size of the databases without a size of their own
+    default: Option<u64>,
+    named: Vec<(String, u64)>,
+}
+
+impl DiskSizes {
+    /// Returns the size of every database of the `layout`, in the layout order
+    pub fn resolve(&self, layout: &DiskLayout) -> Result<Vec<u64>, String> {
+        for (name, _) in &self.named {
+            layout.find(name)?;
+        }
+
+        layout
+            .databases
+            .iter()
+            .map(|database| {
+                let mut named = self
+                    .named
+                    .iter()
+                    .filter(|(name, _)| database.is_named(name));
+                match (named.next(), named.next()) {
+                    (Some((first, _)), Some((second, _))) => Err(format!(
+                        "The {} database is sized both as {} and as {}",
+                        database.key, first, second
+                    )),
+                    (Some((_, size)), None) => Ok(*size),
+                    (None, _) => self
+                        .default
+                        .ok_or_else(|| format!("No size for the {} database", database.key)),
+                }
+            })
+            .collect()
+    }
+}
+
+impl FromStr for DiskSizes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sizes = Self {
+            default: None,
+            named: Vec::new(),
+        };
+        for size in s.split(',') {
+            match size.split_once('=') {
+                Some((name, size)) => sizes
+                    .named
+                    .push((name.trim().to_string(), quantity::parse_size(size)?)),
+                None if sizes.default.is_none() => {
+                    sizes.default = Some(quantity::parse_size(size)?)
+                }
+                None => return Err(format!("Was expecting a single default size, got {}", s)),
+            }
+        }
+        Ok(sizes)
+    }
+}
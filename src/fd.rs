@@ -0,0 +1,124 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{FromRawFd, OwnedFd};
+use std::process;
+use std::str::FromStr;
+
+/// Kind of the file descriptors opened by the fd load
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FdKind {
+    /// Regular files
+    File,
+    /// Both ends of pipes
+    Pipe,
+    /// Both ends of loopback tcp connections, like peer connections
+    Socket,
+}
+
+impl FromStr for FdKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "files" => Ok(FdKind::File),
+            "pipes" => Ok(FdKind::Pipe),
+            "sockets" => Ok(FdKind::Socket),
+            _ => Err(format!("Was expecting files, pipes or sockets, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for FdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FdKind::File => write!(f, "files"),
+            FdKind::Pipe => write!(f, "pipes"),
+            FdKind::Socket => write!(f, "sockets"),
+        }
+    }
+}
+
+/// Parses a comma separated list of fd kinds, e.g. `files,sockets`
+pub fn parse_kinds(value: &str) -> Result<Vec<FdKind>, String> {
+    value.split(',').map(|kind| kind.trim().parse()).collect()
+}
+
+/// Formats the fd kinds the way [parse_kinds] reads them
+pub fn format_kinds(kinds: &[FdKind]) -> String {
+    kinds
+        .iter()
+        .map(|kind| kind.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Raises the soft limit of open files to the hard limit, returns the limit in place
+pub fn raise_fd_limit() -> u64 {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid rlimit for the call to fill
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        panic!("Cannot get the open files limit");
+    }
+    limit.rlim_cur = limit.rlim_max;
+    // SAFETY: the soft limit is raised up to the hard limit only, which needs no privileges
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        panic!("Cannot raise the open files limit");
+    }
+    limit.rlim_cur
+}
+
+/// Opens `count` file descriptors cycling through the `kinds`, stops early when the process or the
+/// system runs out of file descriptors, which is a condition the load is after
+pub fn open_fds(count: usize, kinds: &[FdKind]) -> Vec<OwnedFd> {
+    // every file fd is a duplicate of a single unlinked file, so nothing is left behind
+    let path = format!("monitoring-test-fds-{}", process::id());
+    let file = File::create(&path).unwrap_or_else(|_| panic!("Cannot create fd file: {}", &path));
+    fs::remove_file(&path).unwrap_or_else(|_| panic!("Cannot remove fd file: {}", &path));
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Cannot bind fd socket listener");
+
+    let mut fds: Vec<OwnedFd> = Vec::with_capacity(count);
+    for kind in kinds.iter().cycle() {
+        if fds.len() >= count {
+            break;
+        }
+        let opened = match kind {
+            FdKind::File => file.try_clone().map(|file| vec![file.into()]),
+            FdKind::Pipe => pipe(),
+            FdKind::Socket => connect(&listener),
+        };
+        match opened {
+            Ok(opened) => fds.extend(opened),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE)) => {
+                println!("\tOUT OF FILE DESCRIPTORS AFTER {}: {}", fds.len(), e);
+                break;
+            }
+            Err(e) => panic!("Cannot open {}: {}", kind, e),
+        }
+    }
+    fds
+}
+
+fn pipe() -> io::Result<Vec<OwnedFd>> {
+    let mut ends = [0; 2];
+    // close on exec like every fd opened by std, so the subprocesses do not inherit the pipes
+    // SAFETY: `ends` has room for the two fds written by pipe2
+    if unsafe { libc::pipe2(ends.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: both fds were just opened by pipe2 and are owned by nothing else
+    Ok(ends
+        .iter()
+        .map(|end| unsafe { OwnedFd::from_raw_fd(*end) })
+        .collect())
+}
+
+fn connect(listener: &TcpListener) -> io::Result<Vec<OwnedFd>> {
+    let client = TcpStream::connect(listener.local_addr()?)?;
+    let (server, _) = listener.accept()?;
+    Ok(vec![client.into(), server.into()])
+}
@@ -1,68 +1,229 @@
+use std::cell::Cell;
 use std::convert::TryInto;
-use std::env;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
-use std::mem;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use curl::easy::Easy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener as TokioTcpListener, TcpStream as TokioTcpStream};
 
+use crate::configuration::{Churn, ThreadLoad};
+use crate::controller::CpuController;
+use crate::disk::{
+    self, DiskAllocation, DiskFiles, DiskGrowth, DiskLayout, DiskLinks, DiskShrinkMode,
+};
+use crate::fd::{self, FdKind};
+use crate::memory::{self, GrowthMode, MemoryBlock, MemoryGrowth, MemoryStrategy};
+use crate::profile::{self, LoadProfile};
+use crate::restart::RestartSchedule;
 use crate::rpc;
+use crate::scenario::{Role, Scenario, ScenarioLoad, ScheduledLoad};
+use crate::supervisor;
+use crate::trace::Trace;
 
-pub fn cpu_load(disable_rpc_server: bool) {
+/// Length of one busy/sleep cycle used to generate a fractional cpu load
+const CPU_LOAD_PERIOD: Duration = Duration::from_millis(100);
+
+/// Size of a single read or write of the io loads of a scenario or a trace, the --io-chunk-size
+/// default
+const DEFAULT_IO_CHUNK_SIZE: u64 = 65_536;
+
+/// Size of the file re-read by the io read load of a scenario or a trace, the --io-read-file-size
+/// default
+const DEFAULT_IO_READ_FILE_SIZE: u64 = 67_108_864;
+
+thread_local! {
+    /// End of the load running on this thread, without one the load runs for as long as the node
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Stops the loads run on this thread at the `deadline`
+fn run_until(deadline: Option<Instant>) {
+    DEADLINE.with(|current| current.set(deadline));
+}
+
+/// Returns whether the load running on this thread is still before its deadline
+fn running() -> bool {
+    DEADLINE
+        .with(Cell::get)
+        .is_none_or(|deadline| Instant::now() < deadline)
+}
+
+/// Holds the load running on this thread until its deadline
+fn hold() {
+    match DEADLINE.with(Cell::get) {
+        Some(deadline) => sleep(deadline.saturating_duration_since(Instant::now())),
+        None => sleep(Duration::MAX),
+    }
+}
+
+/// Generates a cpu load of `target` percent of one CPU by spinning for the `target` fraction of
+/// each [CPU_LOAD_PERIOD] and sleeping for the rest of it, the target is scaled by the `profile`
+/// evaluated at the time elapsed since `profile_start`
+///
+/// A `controlled` load corrects the spinning fraction with the cpu time the thread actually got,
+/// see [CpuController]
+pub fn cpu_load(
+    target: f64,
+    profile: LoadProfile,
+    profile_start: SystemTime,
+    controlled: bool,
+    disable_rpc_server: bool,
+) {
     println!("=== CPU SIMULATION STARTED ===\n");
-    println!("\tUSING INFINITE LOOP TO GENERATE 100% load on one CPU");
+    println!(
+        "\tUSING {} DUTY CYCLE TO GENERATE {}% load on one CPU WITH {} PROFILE",
+        if controlled {
+            "CLOSED-LOOP"
+        } else {
+            "OPEN-LOOP"
+        },
+        target,
+        profile
+    );
+    // the cpu test evaluates the profile from the same start
+    println!(
+        "\tCPU PROFILE START: {}",
+        profile::to_unix_millis(profile_start)
+    );
     if !disable_rpc_server {
-        let port = env::var("RPC_PORT")
-            .unwrap_or_else(|_| "18732".to_string())
-            .parse::<u16>()
-            .expect("Expected u16");
-        rpc::spawn_rpc_server(port);
+        rpc::spawn_rpc_server_from_env();
     }
-    loop {
+
+    let target = target / 100.0;
+    let mut controller = controlled.then(CpuController::new);
+
+    while running() {
+        let target = target * profile.fraction(profile::elapsed_since(profile_start));
+        controlled_duty_cycle(controller.as_mut(), target);
+    }
+}
+
+/// Runs a duty cycle at the `target`, corrected by the `controller` of a controlled load
+fn controlled_duty_cycle(controller: Option<&mut CpuController>, target: f64) {
+    match controller {
+        Some(controller) => duty_cycle(controller.busy(target)),
+        None => duty_cycle(target),
+    }
+}
+
+/// Spins for the `busy` fraction of a [CPU_LOAD_PERIOD] and sleeps for the rest of it
+fn duty_cycle(busy: f64) {
+    let busy = CPU_LOAD_PERIOD.mul_f64(busy.clamp(0.0, 1.0));
+    let idle = CPU_LOAD_PERIOD - busy;
+
+    let cycle_start = Instant::now();
+    while cycle_start.elapsed() < busy {
         let _: u128 = 100000 * 255745;
     }
+    if !idle.is_zero() {
+        sleep(idle);
+    }
 }
 
-pub fn memory_load(mem_to_use: usize, disable_rpc_server: bool) {
-    // From std::mem doc: In general, the size of a type is not stable across compilations, but specific types such as primitives are.
-    // u8 should always be a size of 1 Byte
+/// Interval between two allocations of the memory growth
+const MEMORY_GROWTH_TICK: Duration = Duration::from_millis(100);
 
+pub fn memory_load(
+    mem_to_use: usize,
+    strategy: MemoryStrategy,
+    growth: Option<MemoryGrowth>,
+    restart: Option<RestartSchedule>,
+    disable_rpc_server: bool,
+) {
     println!("=== MEMORY SIMULATION STARTED ===\n");
-    println!("\tALLOCATING {} BYTES", mem_to_use);
 
-    let sizeof_bool = mem::size_of::<u8>();
-    let count = mem_to_use / sizeof_bool;
-    let mut artificial_memory_load: Vec<u8> = Vec::with_capacity(count);
-    artificial_memory_load.resize(count, 0);
+    if let Some(growth) = growth {
+        // the growth never finishes, start the subprocess and the rpc server right away
+        if !disable_rpc_server {
+            memory_load_sub_process(mem_to_use, strategy, Some(growth), restart);
+            rpc::spawn_rpc_server_from_env();
+        }
+        memory_growth(mem_to_use, strategy, growth);
+    }
+
+    println!("\tALLOCATING {} BYTES USING {}", mem_to_use, strategy);
+
+    let _artificial_memory_load = MemoryBlock::allocate(strategy, mem_to_use);
 
     println!("\tMEMORY LOADED, STARTING RPC...");
     if !disable_rpc_server {
-        memory_load_sub_process(mem_to_use);
-        let port = env::var("RPC_PORT")
-            .unwrap_or_else(|_| "18732".to_string())
-            .parse::<u16>()
-            .expect("Expected u16");
-        rpc::spawn_rpc_server(port);
+        memory_load_sub_process(mem_to_use, strategy, None, restart);
+        rpc::spawn_rpc_server_from_env();
     }
-    sleep(Duration::MAX);
+    hold();
 }
 
-pub fn network_and_io_load(network_and_io_load_to_use: u64, disable_rpc_server: bool) {
+/// Allocates memory at the growth rate up to the `ceiling`, then holds it or releases it and
+/// starts over, depending on the growth mode
+fn memory_growth(ceiling: usize, strategy: MemoryStrategy, growth: MemoryGrowth) -> ! {
+    println!(
+        "\tGROWING MEMORY BY {} BYTES/s USING {} UP TO {} BYTES, THEN {}",
+        growth.rate, strategy, ceiling, growth.mode
+    );
+
+    let mut allocated = 0;
+    let mut artificial_memory_load: Vec<MemoryBlock> = Vec::new();
+    // bytes due but not allocated yet, a low rate allocates less than a byte per tick
+    let mut due = 0.0;
+
+    loop {
+        let tick_start = Instant::now();
+
+        if allocated < ceiling {
+            due += growth.rate as f64 * MEMORY_GROWTH_TICK.as_secs_f64();
+            let len = (due as usize).min(ceiling - allocated);
+            if len > 0 {
+                // a mapping takes whole pages, growing a single one keeps a slow growth from
+                // rounding every step up to a page and from running out of mappings, the heap
+                // would copy a growing block so it gets a block per step
+                match artificial_memory_load.last_mut() {
+                    Some(block) if strategy != MemoryStrategy::Heap => block.grow(len),
+                    _ => artificial_memory_load.push(MemoryBlock::allocate(strategy, len)),
+                }
+                allocated += len;
+                due -= len as f64;
+            }
+        } else if growth.mode == GrowthMode::Repeat {
+            println!("\tCEILING REACHED, RELEASING {} BYTES", allocated);
+            artificial_memory_load.clear();
+            memory::release_free_heap();
+            allocated = 0;
+            due = 0.0;
+        }
+
+        sleep(MEMORY_GROWTH_TICK.saturating_sub(tick_start.elapsed()));
+    }
+}
+
+/// Downloads from the `network_source` url, or from a local data source streaming at the target
+/// rate when none is provided, and writes the downloaded data to disk
+pub fn network_and_io_load(
+    network_and_io_load_to_use: u64,
+    network_source: Option<String>,
+    disable_rpc_server: bool,
+) {
     println!("=== NETWORK AND IO SIMULATION STARTED ===\n");
     let mut file = File::create("downloaded.file").expect("Cannot create file");
 
+    let url = network_source.unwrap_or_else(|| {
+        println!("\tNO NETWORK SOURCE, THE NODE SENDS AND RECEIVES EVERY BYTE OVER LOOPBACK");
+        let addr = rpc::source::spawn_data_source(network_and_io_load_to_use);
+        format!("http://{}/data", addr)
+    });
+    println!("\tDOWNLOADING FROM {}", url);
+
     let mut easy = Easy::new();
     easy.get(true).expect("Cannot set easy to get request");
-    // TODO: change this to something more appropriate
-    easy.url(
-        "http://65.21.165.81:8080/rt-kernel/linux-image-5.10.41-rt42-dbg_5.10.41-rt42-1_amd64.deb",
-    )
-    .expect("Cannot set url");
+    easy.url(&url).expect("Cannot set url");
     easy.max_recv_speed(network_and_io_load_to_use).expect("Cannot set max bandwidth");
     easy.low_speed_limit(network_and_io_load_to_use.try_into().unwrap())
         .expect("Cannot set min bandwidth");
@@ -75,112 +236,1023 @@ pub fn network_and_io_load(network_and_io_load_to_use: u64, disable_rpc_server:
     .expect("Cannot set write function");
 
     if !disable_rpc_server {
-        let port = env::var("RPC_PORT")
-            .unwrap_or_else(|_| "18732".to_string())
-            .parse::<u16>()
-            .expect("Expected u16");
-        rpc::spawn_rpc_server(port);
+        rpc::spawn_rpc_server_from_env();
     }
 
     easy.perform().expect("Cannot preform request")
 }
 
-pub fn cpu_load_on_threads() {
-    std::thread::Builder::new()
-        .name("test_thread".to_string())
-        .spawn(move || cpu_load(true))
-        .unwrap();
+/// Peers, message sizes and rates of the p2p load
+#[derive(Clone, Copy, Debug)]
+pub struct P2pOptions {
+    /// Number of simulated peers, each one keeps a connection to the listener
+    pub peers: usize,
+    /// Port of the listener, tezos nodes listen on 9732
+    pub port: u16,
+    /// Size of the message payload, without the 2 byte size prefix
+    pub message_size: u16,
+    /// Messages sent per second by every peer
+    pub message_rate: f64,
+}
+
+impl P2pOptions {
+    /// Payload bytes per second going each way over loopback, every message and its echo are
+    /// both sent and received by the node
+    pub fn loopback_rate(&self) -> u64 {
+        let message = 2.0 + self.message_size as f64;
+        (2.0 * self.peers as f64 * self.message_rate * message) as u64
+    }
+
+    /// Messages per second going each way over loopback, counting the echoes
+    pub fn loopback_messages(&self) -> u64 {
+        (2.0 * self.peers as f64 * self.message_rate).ceil() as u64
+    }
+}
+
+/// Simulates tezos p2p traffic, N peers connect to a local listener and send it size prefixed
+/// messages at the message rate, the listener echoes every message back
+pub async fn p2p_load(options: P2pOptions, disable_rpc_server: bool) {
+    println!("=== P2P SIMULATION STARTED ===\n");
+    println!(
+        "\t{} PEERS SENDING {} MESSAGES/s OF {} BYTES TO PORT {}",
+        options.peers, options.message_rate, options.message_size, options.port
+    );
+
+    let listener = TokioTcpListener::bind(("127.0.0.1", options.port))
+        .await
+        .unwrap_or_else(|_| panic!("Cannot bind p2p listener to port {}", options.port));
+    let addr = listener
+        .local_addr()
+        .expect("Cannot get p2p listener address");
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(p2p_echo(stream));
+        }
+    });
+
+    for _ in 0..options.peers {
+        tokio::spawn(p2p_peer(addr, options.message_size, options.message_rate));
+    }
+
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    tokio::time::sleep(Duration::MAX).await;
+}
+
+/// Sends the listener a message on every tick and drains its echoes
+async fn p2p_peer(addr: SocketAddr, message_size: u16, message_rate: f64) {
+    let stream = TokioTcpStream::connect(addr)
+        .await
+        .expect("Cannot connect peer to the p2p listener");
+    let (mut reader, mut writer) = stream.into_split();
+
+    tokio::spawn(async move {
+        let mut message = vec![0u8; 2 + message_size as usize];
+        while reader.read_exact(&mut message).await.is_ok() {}
+    });
+
+    let mut message = Vec::with_capacity(2 + message_size as usize);
+    message.extend_from_slice(&message_size.to_be_bytes());
+    message.resize(2 + message_size as usize, 0xA5);
+
+    let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / message_rate));
+    loop {
+        ticks.tick().await;
+        if writer.write_all(&message).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Reads size prefixed messages from a peer and sends every one of them back
+async fn p2p_echo(mut stream: TokioTcpStream) {
+    let mut message = Vec::new();
+    loop {
+        let mut size = [0u8; 2];
+        if stream.read_exact(&mut size).await.is_err() {
+            break;
+        }
+        message.resize(2 + u16::from_be_bytes(size) as usize, 0);
+        message[..2].copy_from_slice(&size);
+        if stream.read_exact(&mut message[2..]).await.is_err()
+            || stream.write_all(&message).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Size of a single write of the network send load
+const NETWORK_SEND_CHUNK_SIZE: usize = 16_384;
+
+/// Sends data at exactly `rate` bytes per second to the `network_sink` address, or to a local data
+/// sink when none is provided
+pub fn network_send_load(rate: u64, network_sink: Option<String>, disable_rpc_server: bool) {
+    println!("=== NETWORK SEND SIMULATION STARTED ===\n");
+
+    let addr = network_sink.unwrap_or_else(|| {
+        println!("\tNO NETWORK SINK, THE NODE SENDS AND RECEIVES EVERY BYTE OVER LOOPBACK");
+        rpc::sink::spawn_data_sink().to_string()
+    });
+    println!("\tSENDING {} BYTES/s TO {}", rate, addr);
+
+    let mut stream = TcpStream::connect(&addr)
+        .unwrap_or_else(|_| panic!("Cannot connect to network sink: {}", addr));
+
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    let chunk = vec![0xA5u8; NETWORK_SEND_CHUNK_SIZE];
+    let start = Instant::now();
+    let mut sent: u64 = 0;
+
+    while running() {
+        stream
+            .write_all(&chunk)
+            .expect("Cannot send to network sink");
+        sent += NETWORK_SEND_CHUNK_SIZE as u64;
+
+        pace(start, sent, rate);
+    }
+}
+
+/// File rotation and chunking of the io write load
+#[derive(Clone, Copy, Debug)]
+pub struct IoWriteOptions {
+    /// Size of a single write
+    pub chunk_size: u64,
+    /// Size after which the written file is removed and a new one is started
+    pub rotate_size: Option<u64>,
+}
+
+/// Writes to a file in the volume at exactly `rate` bytes per second, independently of any network load
+pub fn io_write_load(
+    rate: u64,
+    options: IoWriteOptions,
+    volume_path: PathBuf,
+    disable_rpc_server: bool,
+) {
+    println!("=== IO WRITE SIMULATION STARTED ===\n");
+    println!(
+        "\tWRITING {} BYTES/s IN CHUNKS OF {} BYTES",
+        rate, options.chunk_size
+    );
+
+    let io_dir = volume_path.join("io-write");
+    fs::create_dir_all(&io_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &io_dir));
+
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    let chunk = vec![0xA5u8; options.chunk_size as usize];
+    let start = Instant::now();
+    let mut written: u64 = 0;
+    let mut file = IoWriteFile::create(io_dir, options.rotate_size);
+
+    while running() {
+        file.write(&chunk);
+        written += options.chunk_size;
+
+        pace(start, written, rate);
+    }
+}
+
+/// The file an io load writes to in its directory, removed and started over once it reaches the
+/// rotate size
+struct IoWriteFile {
+    dir: PathBuf,
+    rotate_size: Option<u64>,
+    index: u64,
+    path: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl IoWriteFile {
+    fn create(dir: PathBuf, rotate_size: Option<u64>) -> Self {
+        let path = dir.join("io-write.0");
+        let file = File::create(&path).expect("Cannot create io write file");
+        Self {
+            dir,
+            rotate_size,
+            index: 0,
+            path,
+            file,
+            written: 0,
+        }
+    }
+
+    /// Writes the `data`, after starting a new file if the current one reached the rotate size
+    fn write(&mut self, data: &[u8]) {
+        if let Some(rotate_size) = self.rotate_size {
+            if self.written >= rotate_size {
+                // flush before removing, removing dirty pages would cancel the accounted writes
+                self.file.sync_data().expect("Cannot sync io write file");
+                fs::remove_file(&self.path).expect("Cannot remove io write file");
+                self.index += 1;
+                self.path = self.dir.join(format!("io-write.{}", self.index));
+                self.file = File::create(&self.path).expect("Cannot create io write file");
+                self.written = 0;
+            }
+        }
+
+        self.file
+            .write_all(data)
+            .expect("Cannot write io write file");
+        self.written += data.len() as u64;
+    }
+}
+
+/// Caching and chunking of the io read load
+#[derive(Clone, Copy, Debug)]
+pub struct IoReadOptions {
+    /// Size of a single read
+    pub chunk_size: u64,
+    /// Size of the prepared file that is read over and over
+    pub file_size: u64,
+    /// Drops every read chunk from the page cache, so the reads hit the storage
+    pub drop_cache: bool,
+}
+
+/// Re-reads a prepared file in the volume at exactly `rate` bytes per second
+pub fn io_read_load(
+    rate: u64,
+    options: IoReadOptions,
+    volume_path: PathBuf,
+    disable_rpc_server: bool,
+) {
+    println!("=== IO READ SIMULATION STARTED ===\n");
+
+    let io_dir = volume_path.join("io-read");
+    fs::create_dir_all(&io_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &io_dir));
+
+    let mut file = IoReadFile::prepare(&io_dir.join("io-read.file"), options);
+
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    println!(
+        "\tREADING {} BYTES/s IN CHUNKS OF {} BYTES{}",
+        rate,
+        options.chunk_size,
+        if options.drop_cache {
+            ", DROPPING THE PAGE CACHE"
+        } else {
+            ""
+        }
+    );
+
+    let mut buffer = vec![0u8; options.chunk_size as usize];
+    let start = Instant::now();
+    let mut read: u64 = 0;
+
+    while running() {
+        read += file.read(&mut buffer) as u64;
+
+        pace(start, read, rate);
+    }
+}
+
+/// The file an io load reads over and over
+struct IoReadFile {
+    file: File,
+    offset: u64,
+    drop_cache: bool,
+}
+
+impl IoReadFile {
+    /// Writes the file read by an io read load and opens it for reading
+    fn prepare(file_path: &Path, options: IoReadOptions) -> Self {
+        println!("\tPREPARING {} BYTES TO READ", options.file_size);
+        let chunk = vec![0xA5u8; options.chunk_size as usize];
+        let mut file = File::create(file_path).expect("Cannot create io read file");
+        let mut prepared = 0;
+        while prepared < options.file_size {
+            let len = options.chunk_size.min(options.file_size - prepared);
+            file.write_all(&chunk[..len as usize])
+                .expect("Cannot write io read file");
+            prepared += len;
+        }
+        file.sync_all().expect("Cannot sync io read file");
+
+        let file = File::open(file_path).expect("Cannot open io read file");
+        if options.drop_cache {
+            // readahead would pull in pages we are about to drop
+            fadvise(&file, 0, 0, libc::POSIX_FADV_RANDOM);
+            fadvise(&file, 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+        Self {
+            file,
+            offset: 0,
+            drop_cache: options.drop_cache,
+        }
+    }
+
+    /// Reads into the `buffer`, starting over at the end of the file, and returns the bytes read
+    fn read(&mut self, buffer: &mut [u8]) -> usize {
+        loop {
+            let len = self.file.read(buffer).expect("Cannot read io read file");
+            if len == 0 {
+                self.file
+                    .seek(SeekFrom::Start(0))
+                    .expect("Cannot rewind io read file");
+                self.offset = 0;
+                continue;
+            }
+            if self.drop_cache {
+                fadvise(
+                    &self.file,
+                    self.offset,
+                    len as u64,
+                    libc::POSIX_FADV_DONTNEED,
+                );
+            }
+            self.offset += len as u64;
+            return len;
+        }
+    }
+}
+
+fn fadvise(file: &File, offset: u64, len: u64, advice: libc::c_int) {
+    // SAFETY: the file descriptor is owned by `file` and stays open for the call
+    let res = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            advice,
+        )
+    };
+    if res != 0 {
+        panic!("Cannot fadvise io read file: {}", res);
+    }
+}
+
+/// Sleeps until `transferred` bytes are due at the `rate` since `start`, pacing on the total
+/// transferred so far keeps the rate from drifting
+fn pace(start: Instant, transferred: u64, rate: u64) {
+    let due = Duration::from_secs_f64(transferred as f64 / rate as f64);
+    if let Some(ahead) = due.checked_sub(start.elapsed()) {
+        sleep(ahead);
+    }
 }
 
-pub fn cpu_load_sub_process() {
+pub fn cpu_load_on_threads(
+    threads: &[ThreadLoad],
+    profile: LoadProfile,
+    profile_start: SystemTime,
+    controlled: bool,
+) {
+    // the threads stop with the load spawning them
+    let deadline = DEADLINE.with(Cell::get);
+    for thread in threads {
+        let target = thread.target;
+        std::thread::Builder::new()
+            .name(thread.name.clone())
+            .spawn(move || {
+                run_until(deadline);
+                cpu_load(target, profile, profile_start, controlled, true)
+            })
+            .unwrap_or_else(|_| panic!("Cannot spawn thread {}", thread.name));
+    }
+}
+
+/// Keeps `idle` sleeping threads alive and spawns short-lived threads at the `churn` rate
+pub fn thread_load(idle: usize, churn: Option<Churn>, disable_rpc_server: bool) {
+    println!("=== THREAD SIMULATION STARTED ===\n");
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    println!("\tKEEPING {} IDLE THREADS", idle);
+    for index in 0..idle {
+        let name = format!("idle-{}", index);
+        std::thread::Builder::new()
+            .name(name.clone())
+            .spawn(|| sleep(Duration::MAX))
+            .unwrap_or_else(|_| panic!("Cannot spawn thread {}", name));
+    }
+
+    if let Some(churn) = churn {
+        thread_churn(churn);
+    }
+    sleep(Duration::MAX);
+}
+
+/// Spawns a thread every 1 / rate seconds, each one spinning for the burn duration and exiting
+fn thread_churn(churn: Churn) -> ! {
+    println!(
+        "\tSPAWNING {} THREADS/s BURNING {:?} OF CPU EACH",
+        churn.rate, churn.burn
+    );
+
+    let interval = Duration::from_secs_f64(1.0 / churn.rate);
+    let start = Instant::now();
+    let mut spawned: u64 = 0;
+
+    loop {
+        let burn = churn.burn;
+        // the names repeat, the thread ids do not
+        std::thread::Builder::new()
+            .name(format!("churn-{}", spawned % 1000))
+            .spawn(move || cpu_burn(burn))
+            .expect("Cannot spawn churn thread");
+        spawned += 1;
+
+        // paced on the start, so the time spent spawning does not lower the rate
+        if let Some(ahead) = interval
+            .mul_f64(spawned as f64)
+            .checked_sub(start.elapsed())
+        {
+            sleep(ahead);
+        }
+    }
+}
+
+/// Environment variable holding the burn of a fork storm subprocess, the executable run with it
+/// only burns cpu and exits
+pub const FORK_STORM_BURN: &str = "FORK_STORM_BURN";
+
+/// Name of the fork storm subprocesses
+pub const FORK_STORM_NAME: &str = "fork-storm";
+
+/// Spawns a subprocess every 1 / rate seconds, each one burning cpu for the burn duration and
+/// exiting, the supervisor reaps the exited subprocesses so their cpu time is accounted to the node
+pub fn fork_storm(churn: Churn, disable_rpc_server: bool) {
+    println!("=== FORK STORM SIMULATION STARTED ===\n");
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+    println!(
+        "\tSPAWNING {} SUBPROCESSES/s BURNING {:?} OF CPU EACH",
+        churn.rate, churn.burn
+    );
+
+    let interval = Duration::from_secs_f64(1.0 / churn.rate);
+    let burn = format!("{}ms", churn.burn.as_secs_f64() * 1000.0);
+    let start = Instant::now();
+    let mut spawned: u64 = 0;
+
+    loop {
+        supervisor::spawn(supervisor::command().env(FORK_STORM_BURN, &burn));
+        spawned += 1;
+
+        // paced on the start, so the time spent spawning does not lower the rate
+        if let Some(ahead) = interval
+            .mul_f64(spawned as f64)
+            .checked_sub(start.elapsed())
+        {
+            sleep(ahead);
+        }
+    }
+}
+
+/// Spins for `burn`, the work of a single churned thread
+pub fn cpu_burn(burn: Duration) {
+    let burn_start = Instant::now();
+    while burn_start.elapsed() < burn {
+        let _: u128 = 100000 * 255745;
+    }
+}
+
+/// Spins until the process used `burn` of cpu time, the work of a single fork storm subprocess,
+/// the cpu time of the exec and of the start of the process counts towards its burn
+pub fn process_cpu_burn(burn: Duration) {
+    while process_cpu_clock() < burn {
+        let _: u128 = 100000 * 255745;
+    }
+}
+
+/// Returns the cpu time used by this process since it was forked, with nanosecond resolution
+fn process_cpu_clock() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: clock_gettime only writes the timespec it is given
+    unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+pub fn cpu_load_sub_process(
+    target: f64,
+    profile: LoadProfile,
+    profile_start: SystemTime,
+    controlled: bool,
+    restart: Option<RestartSchedule>,
+) {
     println!("\tSTARTING SUBRPOCESS");
-    Command::new("/monitoring-test")
-        .args(&[
-            "--cpu-load",
-            "--disable-rpc-server",
-            "--process-name",
-            "protocol-runner",
-        ])
-        .spawn()
-        .expect("Cannot run subprocess");
-}
-
-pub fn memory_load_sub_process(target: usize) {
+    let mut command = supervisor::command();
+    command.args([
+        "--cpu-load",
+        &target.to_string(),
+        "--cpu-profile",
+        &profile.to_string(),
+        "--cpu-profile-start",
+        &profile::to_unix_millis(profile_start).to_string(),
+        "--disable-rpc-server",
+        "--process-name",
+        "protocol-runner",
+    ]);
+    if controlled {
+        command.arg("--cpu-controller");
+    }
+    spawn_sub_process(command, restart);
+}
+
+pub fn memory_load_sub_process(
+    target: usize,
+    strategy: MemoryStrategy,
+    growth: Option<MemoryGrowth>,
+    restart: Option<RestartSchedule>,
+) {
+    println!("\tSTARTING SUBRPOCESS");
+    let mut command = supervisor::command();
+    command.args([
+        "--memory-load",
+        &target.to_string(),
+        "--memory-strategy",
+        &strategy.to_string(),
+        "--disable-rpc-server",
+        "--process-name",
+        "protocol-runner",
+    ]);
+    if let Some(growth) = growth {
+        command.args([
+            "--memory-growth-rate",
+            &growth.rate.to_string(),
+            "--memory-growth-mode",
+            &growth.mode.to_string(),
+        ]);
+    }
+    spawn_sub_process(command, restart);
+}
+
+/// Spawns the protocol-runner `command` and kills and respawns it with a new pid on the `restart`
+/// schedule, like a crashing protocol runner
+fn spawn_sub_process(mut command: Command, restart: Option<RestartSchedule>) {
+    let mut pid = supervisor::spawn(&mut command);
+
+    if let Some(restart) = restart {
+        println!("\tRESTARTING SUBPROCESS ON {} SCHEDULE", restart);
+        // the subprocesses die with the thread spawning them, which runs for as long as the node
+        std::thread::Builder::new()
+            .name("respawner".to_string())
+            .spawn(move || {
+                for delay in restart.delays() {
+                    sleep(delay);
+                    supervisor::kill(pid);
+                    let crashed = pid;
+                    pid = supervisor::spawn(&mut command);
+                    println!("\tSUBPROCESS {} RESPAWNED AS {}", crashed, pid);
+                }
+            })
+            .expect("Cannot spawn thread respawner");
+    }
+}
+
+/// Opens `count` file descriptors of the `kinds` in the node and in a protocol-runner subprocess
+/// and holds them, up to running out of file descriptors
+pub fn fd_load(count: usize, kinds: &[FdKind], disable_rpc_server: bool) {
+    println!("=== FILE DESCRIPTOR SIMULATION STARTED ===\n");
+
+    // the subprocess and the rpc server need fds of their own, start them before running out
+    if !disable_rpc_server {
+        fd_load_sub_process(count, kinds);
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    println!("\tOPEN FILES LIMIT: {}", fd::raise_fd_limit());
+    println!("\tOPENING {} FDS AS {}", count, fd::format_kinds(kinds));
+
+    let artificial_fd_load = fd::open_fds(count, kinds);
+
+    println!("\tOPENED {} FDS", artificial_fd_load.len());
+    hold();
+}
+
+pub fn fd_load_sub_process(count: usize, kinds: &[FdKind]) {
     println!("\tSTARTING SUBRPOCESS");
-    Command::new("/monitoring-test")
-        .args(&[
-            "--memory-load",
-            &target.to_string(),
-            "--disable-rpc-server",
-            "--process-name",
-            "protocol-runner",
-        ])
-        .spawn()
-        .expect("Cannot run subprocess");
+    supervisor::spawn(supervisor::command().args([
+        "--fd-load",
+        &count.to_string(),
+        "--fd-kinds",
+        &fd::format_kinds(kinds),
+        "--disable-rpc-server",
+        "--process-name",
+        "protocol-runner",
+    ]));
 }
 
+/// Runs every load of the `scenario` at the same time, each one from its start offset for its
+/// duration, in the node or in a protocol-runner subprocess of its own
+/// The cpu loads are `controlled` like the cpu load, in the node and in the subprocesses
+pub fn scenario_load(
+    scenario: &Scenario,
+    volume_path: PathBuf,
+    controlled: bool,
+    disable_rpc_server: bool,
+) {
+    println!("=== SCENARIO SIMULATION STARTED ===\n");
+    println!(
+        "\tRUNNING {} LOADS OF {}",
+        scenario.loads.len(),
+        scenario.name
+    );
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    // the network send load spawns its data sink on the runtime of the node
+    let runtime = tokio::runtime::Handle::current();
+    let scenario_start = Instant::now();
+    for (index, scheduled) in scenario.loads.iter().enumerate() {
+        let scheduled = scheduled.clone();
+        let runtime = runtime.clone();
+        // every load gets a directory of its own, so two io loads never share a file
+        let volume_path = volume_path.join(format!("scenario-{}", index));
+        // a subprocess dies with the thread spawning it, so the thread lives as long as the load
+        std::thread::Builder::new()
+            .name(format!("scenario-{}", index))
+            .spawn(move || {
+                let _runtime = runtime.enter();
+                sleep((scenario_start + scheduled.start).saturating_duration_since(Instant::now()));
+                run_until(scheduled.duration.map(|duration| Instant::now() + duration));
+                scheduled_load(index, &scheduled, volume_path, controlled);
+                println!("\tLOAD {} OF THE SCENARIO STOPPED", index);
+            })
+            .unwrap_or_else(|_| panic!("Cannot spawn thread scenario-{}", index));
+    }
+    sleep(Duration::MAX);
+}
+
+/// Runs the `scheduled` load of a scenario up to the deadline of this thread
+fn scheduled_load(index: usize, scheduled: &ScheduledLoad, volume_path: PathBuf, controlled: bool) {
+    println!(
+        "\tSTARTING LOAD {} OF THE SCENARIO IN THE {}: {:?}",
+        index,
+        match scheduled.role {
+            Role::Node => "NODE",
+            Role::Subprocess => "SUBPROCESS",
+        },
+        scheduled.load
+    );
+    match (scheduled.role, &scheduled.load) {
+        (Role::Subprocess, load) => {
+            println!("\tSTARTING SUBRPOCESS");
+            let mut command = supervisor::command();
+            command
+                .args(load.args())
+                .args(["--disable-rpc-server", "--process-name", "protocol-runner"])
+                .env("VOLUME_PATH", &volume_path);
+            if controlled && matches!(load, ScenarioLoad::Cpu(_)) {
+                command.arg("--cpu-controller");
+            }
+            let pid = supervisor::spawn(&mut command);
+            hold();
+            supervisor::kill(pid);
+        }
+        (Role::Node, ScenarioLoad::Cpu(threads)) => {
+            cpu_load_on_threads(
+                threads,
+                LoadProfile::Constant,
+                SystemTime::now(),
+                controlled,
+            );
+            hold();
+        }
+        (Role::Node, ScenarioLoad::Memory(size)) => {
+            memory_load(*size, MemoryStrategy::default(), None, None, true)
+        }
+        (Role::Node, ScenarioLoad::IoWrite(rate)) => io_write_load(
+            *rate,
+            IoWriteOptions {
+                chunk_size: DEFAULT_IO_CHUNK_SIZE,
+                rotate_size: None,
+            },
+            volume_path,
+            true,
+        ),
+        (Role::Node, ScenarioLoad::IoRead(rate)) => io_read_load(
+            *rate,
+            IoReadOptions {
+                chunk_size: DEFAULT_IO_CHUNK_SIZE,
+                file_size: DEFAULT_IO_READ_FILE_SIZE,
+                drop_cache: true,
+            },
+            volume_path,
+            true,
+        ),
+        (Role::Node, ScenarioLoad::NetworkSend(rate)) => network_send_load(*rate, None, true),
+        (Role::Node, ScenarioLoad::Fds(count)) => {
+            fd_load(*count, &[FdKind::File, FdKind::Pipe, FdKind::Socket], true)
+        }
+    }
+}
+
+/// Interval between two updates of the memory, io and disk loads replaying a trace
+const TRACE_TICK: Duration = Duration::from_millis(100);
+
+/// Size of the blocks allocated and released to follow the memory of a trace
+const TRACE_MEMORY_BLOCK: usize = 1_048_576;
+
+/// Size after which the file written by the io of a trace is removed and a new one is started
+const TRACE_IO_ROTATE_SIZE: u64 = 67_108_864;
+
+/// Pace of a trace replay and how it loads the cpu and the disk
+#[derive(Clone, Copy, Debug)]
+pub struct TraceOptions {
+    /// How many times faster than recorded the trace is replayed
+    pub speed: f64,
+    /// Start of the replay, the trace test compares the monitoring history from it
+    pub start: SystemTime,
+    /// How the files of the databases are allocated
+    pub allocation: DiskAllocation,
+    /// Holds the cpu load of every thread with a controller, like the cpu load
+    pub controlled: bool,
+}
+
+/// Replays the `trace` from the start of the `options`: the cpu load of every thread, the memory
+/// and the io of the node and the size of every database, each sample is held up to the next one
+/// and the last one after the end of the trace
+pub fn trace_load(
+    trace: Trace,
+    options: TraceOptions,
+    layout: &DiskLayout,
+    volume_path: PathBuf,
+    disable_rpc_server: bool,
+) {
+    println!("=== TRACE REPLAY STARTED ===\n");
+    println!(
+        "\tREPLAYING {} SAMPLES OF {} IN {:?}, {}x FASTER THAN RECORDED",
+        trace.samples.len(),
+        trace.name,
+        trace.duration().div_f64(options.speed),
+        options.speed
+    );
+    // the trace test compares the monitoring history from the same start
+    println!("\tTRACE START: {}", profile::to_unix_millis(options.start));
+
+    recreate_dir(&volume_path);
+    let databases: Vec<(String, PathBuf)> = trace
+        .disk_names()
+        .into_iter()
+        .map(|name| {
+            let database = layout.find(&name).unwrap_or_else(|e| panic!("{}", e));
+            let database_path = volume_path.join(&database.path);
+            fs::create_dir_all(&database_path)
+                .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &database_path));
+            let file_path = database_path.join("dummy.db");
+            disk::create_dummy_file(&file_path, 0, options.allocation);
+            (name, file_path)
+        })
+        .collect();
+
+    if !disable_rpc_server {
+        rpc::spawn_rpc_server_from_env();
+    }
+
+    let trace = Arc::new(trace);
+    let offset = move || profile::elapsed_since(options.start).mul_f64(options.speed);
+
+    for name in trace.thread_names() {
+        let trace = trace.clone();
+        let thread_name = name.clone();
+        std::thread::Builder::new()
+            .name(thread_name)
+            .spawn(move || {
+                let mut controller = options.controlled.then(CpuController::new);
+                loop {
+                    let target = trace.at(offset()).thread_target(&name) / 100.0;
+                    controlled_duty_cycle(controller.as_mut(), target);
+                }
+            })
+            .expect("Cannot spawn trace thread");
+    }
+
+    let replays_io = trace
+        .samples
+        .iter()
+        .any(|sample| sample.io_read.is_some() || sample.io_written.is_some());
+    if replays_io {
+        let trace = trace.clone();
+        let io_dir = volume_path.join("trace-io");
+        std::thread::Builder::new()
+            .name("trace-io".to_string())
+            .spawn(move || trace_io(&trace, offset, &io_dir))
+            .expect("Cannot spawn thread trace-io");
+    }
+
+    let mut memory: Vec<MemoryBlock> = Vec::new();
+    let mut sizes = vec![0; databases.len()];
+    loop {
+        let tick_start = Instant::now();
+        let sample = trace.at(offset());
+
+        let blocks = sample.memory.unwrap_or(0) as usize / TRACE_MEMORY_BLOCK;
+        if memory.len() > blocks {
+            memory.truncate(blocks);
+            memory::release_free_heap();
+        }
+        while memory.len() < blocks {
+            memory.push(MemoryBlock::allocate(
+                MemoryStrategy::default(),
+                TRACE_MEMORY_BLOCK,
+            ));
+        }
+
+        for ((name, path), size) in databases.iter().zip(sizes.iter_mut()) {
+            let target = sample.disk_size(name).unwrap_or(0);
+            if target > *size {
+                disk::grow_dummy_file(path, target - *size, options.allocation);
+            } else if target < *size {
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .and_then(|file| file.set_len(target))
+                    .unwrap_or_else(|_| panic!("Failed to truncate file: {:?}", path));
+            }
+            *size = target;
+        }
+
+        sleep(TRACE_TICK.saturating_sub(tick_start.elapsed()));
+    }
+}
+
+/// Writes and reads in the `io_dir` at the io rates of the `trace` sample at the `offset`, the
+/// reads drop the page cache so they hit the storage
+fn trace_io(trace: &Trace, offset: impl Fn() -> Duration, io_dir: &Path) -> ! {
+    fs::create_dir_all(io_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", io_dir));
+
+    let options = IoReadOptions {
+        chunk_size: DEFAULT_IO_CHUNK_SIZE,
+        file_size: DEFAULT_IO_READ_FILE_SIZE,
+        drop_cache: true,
+    };
+    let mut read_file = trace
+        .samples
+        .iter()
+        .any(|sample| sample.io_read.is_some())
+        .then(|| IoReadFile::prepare(&io_dir.join("io-read.file"), options));
+
+    let chunk = vec![0xA5u8; DEFAULT_IO_CHUNK_SIZE as usize];
+    let mut buffer = vec![0u8; DEFAULT_IO_CHUNK_SIZE as usize];
+    let mut file = IoWriteFile::create(io_dir.to_path_buf(), Some(TRACE_IO_ROTATE_SIZE));
+    // bytes due but not transferred yet, a low rate transfers less than a byte per tick
+    let mut write_due = 0.0;
+    let mut read_due = 0.0;
+
+    loop {
+        let tick_start = Instant::now();
+        let sample = trace.at(offset());
+
+        write_due += sample.io_written.unwrap_or(0) as f64 * TRACE_TICK.as_secs_f64();
+        while write_due >= 1.0 {
+            let len = (write_due as usize).min(chunk.len());
+            file.write(&chunk[..len]);
+            write_due -= len as f64;
+        }
+
+        if let Some(read_file) = read_file.as_mut() {
+            read_due += sample.io_read.unwrap_or(0) as f64 * TRACE_TICK.as_secs_f64();
+            while read_due >= 1.0 {
+                let len = (read_due as usize).min(buffer.len());
+                read_due -= read_file.read(&mut buffer[..len]) as f64;
+            }
+        }
+
+        sleep(TRACE_TICK.saturating_sub(tick_start.elapsed()));
+    }
+}
+
+/// Interval between two appends to the dummy database files
+const DISK_GROWTH_TICK: Duration = Duration::from_secs(1);
+
 /// Create dummy files of defined size to simulate databse sizes
-pub fn disk_load(disk_load: u64, volume_path: PathBuf) {
+pub fn disk_load(
+    disk_load: &[u64],
+    allocation: DiskAllocation,
+    layout: &DiskLayout,
+    files: DiskFiles,
+    links: Option<&DiskLinks>,
+    growth: Option<DiskGrowth>,
+    volume_path: PathBuf,
+) {
     println!("=== DISK DATABSE SIZE SIMULATION STARTED ===\n");
+    println!("\tUSING {} LAYOUT", layout.name);
+    println!("\tALLOCATING FILES AS {}", allocation);
+    println!(
+        "\tSPREADING EVERY DB OVER {} FILES, {} PER DIRECTORY",
+        files.count, files.per_dir
+    );
+
+    if let Some(links) = links {
+        // a mount left by a previous run would keep the volume from being removed
+        links.unmount(layout, &volume_path);
+    }
+    recreate_dir(&volume_path);
+    if let Some(links) = links {
+        println!(
+            "\tLINKING FILES OUTSIDE OF THE DATABASES, {} DB SYMLINKED",
+            links.symlinked
+        );
+        if let Some(mounted) = &links.mounted {
+            println!("\tMOUNTING THE FILES OF {} DB INTO IT", mounted);
+        }
+        recreate_dir(&DiskLinks::outside_databases(&volume_path));
+    }
+
+    let mut dummy_files = Vec::new();
+    for (database, size) in layout.databases.iter().zip(disk_load) {
+        println!(
+            "\tCREATING {} DB OF {} BYTES IN {:?}",
+            database.key, size, database.path
+        );
+        let files_dir = match links {
+            Some(links) => links.create_dir(database, &volume_path),
+            None => {
+                let database_path = volume_path.join(&database.path);
+                fs::create_dir_all(&database_path)
+                    .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &database_path));
+                database_path
+            }
+        };
 
-    if Path::new(&volume_path).exists() {
-        fs::remove_dir_all(&volume_path)
-            .unwrap_or_else(|_| panic!("Failed to remove directory: {:?}", &volume_path));
-        fs::create_dir_all(&volume_path)
-            .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &volume_path));
-    } else {
-        fs::create_dir_all(&volume_path)
-            .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &volume_path));
-    }
-
-    println!("\tCREATING CONTEXT STATS DB");
-    let context_stats = volume_path.join("context-stats-db");
-    fs::create_dir_all(&context_stats).unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &context_stats));
-
-    let context_file =
-        fs::File::create(context_stats.join("dummy.db")).expect("Failed to create context file");
-    context_file
-        .set_len(disk_load)
-        .expect("Failed to set context file length");
-
-    println!("\tCREATING CONTEXT DB");
-    let context_storage = volume_path.join("context");
-    fs::create_dir_all(&context_storage)
-        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &context_storage));
-
-    let context_storage_file = fs::File::create(context_storage.join("dummy.db"))
-        .expect("Failed to create context_storage_file");
-    context_storage_file
-        .set_len(disk_load)
-        .expect("Failed to set context_storage_file length");
-
-    println!("\tCREATING BLOCK STORAGE DB");
-    let block_storage = volume_path.join("bootstrap_db/block_storage");
-    fs::create_dir_all(&block_storage)
-        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &block_storage));
-
-    let block_storage_file = fs::File::create(block_storage.join("dummy.db"))
-        .expect("Failed to create block_storage_file");
-    block_storage_file
-        .set_len(disk_load)
-        .expect("Failed to set block_storage_file length");
-
-    println!("\tCREATING MAIN DB");
-    let main_db_storage = volume_path.join("bootstrap_db/db");
-    fs::create_dir_all(&main_db_storage).unwrap_or_else(|_| panic!("Failed to create directory: {:?}", &main_db_storage));
-
-    let main_db_file =
-        fs::File::create(main_db_storage.join("dummy.db")).expect("Failed to create main_db_file");
-    main_db_file
-        .set_len(disk_load)
-        .expect("Failed to set main_db_file length");
+        // the last file of every database is the one that grows, like an active segment
+        dummy_files.push(files.create(&files_dir, *size, allocation));
+
+        if let Some(links) = links {
+            links.link_files(database, &files_dir, &volume_path, *size, files, allocation);
+        }
+    }
 
     // launch rpc port
-    let port = env::var("RPC_PORT")
-        .unwrap_or_else(|_| "18732".to_string())
-        .parse::<u16>()
-        .expect("Expected u16");
-    rpc::spawn_rpc_server(port);
+    rpc::spawn_rpc_server_from_env();
 
+    if let Some(growth) = growth {
+        disk_growth(&dummy_files, allocation, growth);
+    }
     sleep(Duration::MAX);
 }
+
+/// Removes the directory at `path` with everything in it and creates it empty
+fn recreate_dir(path: &Path) {
+    if path.exists() {
+        fs::remove_dir_all(path)
+            .unwrap_or_else(|_| panic!("Failed to remove directory: {:?}", path));
+    }
+    fs::create_dir_all(path).unwrap_or_else(|_| panic!("Failed to create directory: {:?}", path));
+}
+
+/// Appends to the dummy database `files` (with their initial size) at the growth rate, and
+/// truncates them back to their initial size or deletes them on the shrink schedule
+fn disk_growth(files: &[(PathBuf, u64)], allocation: DiskAllocation, growth: DiskGrowth) -> ! {
+    println!("\tGROWING EVERY DB BY {} BYTES/s", growth.rate);
+    if let Some(shrink) = growth.shrink {
+        println!(
+            "\tSHRINKING EVERY {:?} WITH {}",
+            shrink.interval, shrink.mode
+        );
+    }
+
+    let chunk = (growth.rate as f64 * DISK_GROWTH_TICK.as_secs_f64()) as u64;
+    let mut last_shrink = Instant::now();
+
+    loop {
+        let tick_start = Instant::now();
+
+        match growth.shrink {
+            Some(shrink) if last_shrink.elapsed() >= shrink.interval => {
+                println!("\tSHRINKING DBS WITH {}", shrink.mode);
+                for (path, size) in files {
+                    match shrink.mode {
+                        DiskShrinkMode::Truncate => OpenOptions::new()
+                            .write(true)
+                            .open(path)
+                            .and_then(|file| file.set_len(*size))
+                            .unwrap_or_else(|_| panic!("Failed to truncate file: {:?}", path)),
+                        DiskShrinkMode::Delete => fs::remove_file(path)
+                            .unwrap_or_else(|_| panic!("Failed to remove file: {:?}", path)),
+                    }
+                }
+                last_shrink = Instant::now();
+            }
+            _ => {
+                for (path, _) in files {
+                    disk::grow_dummy_file(path, chunk, allocation);
+                }
+            }
+        }
+
+        sleep(DISK_GROWTH_TICK.saturating_sub(tick_start.elapsed()));
+    }
+}
@@ -1,20 +1,48 @@
 
 use std::env;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 pub mod configuration;
+pub mod controller;
+pub mod disk;
+pub mod fd;
 pub mod loads;
+pub mod memory;
+pub mod profile;
+pub mod quantity;
+pub mod restart;
 pub mod rpc;
+pub mod scenario;
+pub mod supervisor;
 pub mod tests;
+pub mod trace;
 
 use crate::loads::*;
 use crate::tests::*;
-use configuration::MonitoringTestEnvironment;
+use configuration::{MonitoringTestEnvironment, ThreadLoad};
 
-#[tokio::main]
-async fn main() {
+fn main() {
+    // a fork storm subprocess only burns cpu and exits, it neither parses the arguments nor
+    // starts a runtime
+    if let Ok(burn) = env::var(FORK_STORM_BURN) {
+        prctl::set_name(FORK_STORM_NAME).expect("Cannot change process name");
+        return process_cpu_burn(quantity::parse_duration(&burn).expect("Expected duration"));
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Cannot start the runtime")
+        .block_on(run())
+}
+
+async fn run() {
     let env = MonitoringTestEnvironment::from_args();
 
+    // every subprocess is reaped and gets the SIGTERM or SIGINT of the node
+    supervisor::start();
+
     let volume_path = env::var("VOLUME_PATH")
         .unwrap_or_else(|_| "/tmp/tezedge".to_string())
         .parse::<PathBuf>()
@@ -25,44 +53,226 @@ async fn main() {
             .unwrap_or_else(|_| panic!("Cannot change proces name to {}", process_name));
     }
 
-    if env.cpu_load {
-        // launch a thread with 100% load (this also loads the main process to 100%)
-        cpu_load_on_threads();
+    // every load following the cpu profile shares the same start, subprocesses get it from the node
+    let cpu_profile_start = env.cpu_profile_start.unwrap_or_else(SystemTime::now);
+
+    if env.cpu_controller {
+        // the controlled loads know what the monitoring is expected to measure
+        tokio::spawn(controller::report_cpu_load());
+    }
+
+    if let Some(scenario) = env.scenario {
+        scenario_load(
+            &scenario,
+            volume_path,
+            env.cpu_controller,
+            env.disable_rpc_server,
+        )
+    } else if let Some(trace) = env.trace {
+        trace_load(
+            trace,
+            TraceOptions {
+                speed: env.trace_speed,
+                start: env.trace_start.unwrap_or_else(SystemTime::now),
+                allocation: env.disk_allocation,
+                controlled: env.cpu_controller,
+            },
+            &env.disk_layout,
+            volume_path,
+            env.disable_rpc_server,
+        )
+    } else if let Some(cpu_target) = env.cpu_load {
+        // launch the threads with their target loads (the main process is loaded by their sum)
+        cpu_load_on_threads(
+            &threads_or_default(env.cpu_threads, cpu_target),
+            env.cpu_profile,
+            cpu_profile_start,
+            env.cpu_controller,
+        );
         if !env.disable_rpc_server {
             // the rpc server is disabled only when a subprocess is run, so run the subprocess here
             // this allows us to test every aspect of the cpu measurements (collective, thread, subprocess)
-            cpu_load_sub_process();
-            let port = env::var("RPC_PORT")
-                .unwrap_or_else(|_| "18732".to_string())
-                .parse::<u16>()
-                .expect("Expected u16");
-            rpc::spawn_rpc_server(port);
+            cpu_load_sub_process(
+                cpu_target,
+                env.cpu_profile,
+                cpu_profile_start,
+                env.cpu_controller,
+                env.subprocess_restart,
+            );
+            rpc::spawn_rpc_server_from_env();
         }
         tokio::time::sleep(tokio::time::Duration::MAX).await;
     } else if let Some(mem_to_use) = env.memory_load {
-        memory_load(mem_to_use, env.disable_rpc_server)
+        memory_load(
+            mem_to_use,
+            env.memory_strategy,
+            env.memory_growth,
+            env.subprocess_restart,
+            env.disable_rpc_server,
+        )
+    } else if env.idle_threads.is_some() || env.thread_churn.is_some() {
+        thread_load(
+            env.idle_threads.unwrap_or(0),
+            env.thread_churn,
+            env.disable_rpc_server,
+        )
+    } else if let Some(churn) = env.fork_storm {
+        fork_storm(churn, env.disable_rpc_server)
+    } else if let Some(fds) = env.fd_load {
+        fd_load(fds, &env.fd_kinds, env.disable_rpc_server)
     } else if let Some(network_and_io_load_to_use) = env.network_and_io_load {
-        network_and_io_load(network_and_io_load_to_use, env.disable_rpc_server);
+        network_and_io_load(
+            network_and_io_load_to_use,
+            env.network_source,
+            env.disable_rpc_server,
+        );
+    } else if let Some(peers) = env.p2p_peers {
+        p2p_load(
+            P2pOptions {
+                peers,
+                port: env.p2p_port,
+                message_size: env.p2p_message_size,
+                message_rate: env.p2p_message_rate,
+            },
+            env.disable_rpc_server,
+        )
+        .await;
+    } else if let Some(network_send_load_to_use) = env.network_send_load {
+        network_send_load(
+            network_send_load_to_use,
+            env.network_sink,
+            env.disable_rpc_server,
+        );
+    } else if let Some(io_write_load_to_use) = env.io_write_load {
+        io_write_load(
+            io_write_load_to_use,
+            IoWriteOptions {
+                chunk_size: env.io_chunk_size,
+                rotate_size: env.io_rotate_size,
+            },
+            volume_path,
+            env.disable_rpc_server,
+        );
+    } else if let Some(io_read_load_to_use) = env.io_read_load {
+        io_read_load(
+            io_read_load_to_use,
+            IoReadOptions {
+                chunk_size: env.io_chunk_size,
+                file_size: env.io_read_file_size,
+                drop_cache: env.io_drop_cache,
+            },
+            volume_path,
+            env.disable_rpc_server,
+        );
     } else if let Some(cpu_target) = env.test_cpu {
-        test_cpu(cpu_target).await;
+        test_cpu(
+            cpu_target,
+            &threads_or_default(env.test_cpu_threads, cpu_target),
+            env.test_cpu_error_margin,
+            env.cpu_profile,
+            cpu_profile_start,
+        )
+        .await;
+    } else if let Some(idle) = env.test_threads {
+        test_threads(idle, env.test_thread_churn).await;
+    } else if let Some(churn) = env.test_fork_storm {
+        test_fork_storm(churn).await;
+    } else if let Some(samples) = env.test_subprocess_restart {
+        test_subprocess_restart(samples).await;
     } else if let Some(memory_target) = env.test_memory {
-        test_memory(memory_target).await;
+        test_memory(
+            memory_target,
+            env.memory_strategy,
+            env.test_memory_semantics,
+        )
+        .await;
+    } else if let Some(samples) = env.test_fd_load {
+        test_fd_load(samples).await;
     } else if let Some(io_network_target) = env.test_network_and_io {
         test_network_and_io(io_network_target).await;
+    } else if let Some(io_target) = env.test_io_write {
+        test_io_write(io_target).await;
+    } else if let Some(network_target) = env.test_network {
+        test_network(network_target).await;
+    } else if let Some(io_target) = env.test_io_read {
+        test_io_read(io_target).await;
+    } else if let Some(network_target) = env.test_network_sent {
+        test_network_sent(network_target).await;
+    } else if let Some(peers) = env.test_p2p {
+        test_p2p(P2pOptions {
+            peers,
+            port: env.p2p_port,
+            message_size: env.p2p_message_size,
+            message_rate: env.p2p_message_rate,
+        })
+        .await;
     } else if env.cpu_load_with_subprocess {
-        cpu_load_sub_process();
+        cpu_load_sub_process(
+            100.0,
+            env.cpu_profile,
+            cpu_profile_start,
+            env.cpu_controller,
+            env.subprocess_restart,
+        );
         // cpu_load_on_threads();
         if !env.disable_rpc_server {
-            let port = env::var("RPC_PORT")
-                .unwrap_or_else(|_| "18732".to_string())
-                .parse::<u16>()
-                .expect("Expected u16");
-            rpc::spawn_rpc_server(port);
+            rpc::spawn_rpc_server_from_env();
         }
         tokio::time::sleep(tokio::time::Duration::MAX).await;
     } else if let Some(disk_target) = env.disk_load {
-        disk_load(disk_target, volume_path);
+        disk_load(
+            &disk_target,
+            env.disk_allocation,
+            &env.disk_layout,
+            env.disk_files,
+            env.disk_links.as_ref(),
+            env.disk_growth,
+            volume_path,
+        );
     } else if let Some(target) = env.test_disk {
-        test_disk_size(target).await;
+        if let Some(growth) = env.disk_growth {
+            test_disk_growth(
+                &target,
+                env.disk_allocation,
+                env.test_disk_semantics,
+                &env.disk_layout,
+                env.disk_files,
+                growth,
+            )
+            .await;
+        } else {
+            test_disk_size(
+                &target,
+                env.disk_allocation,
+                env.test_disk_semantics,
+                &env.disk_layout,
+                env.disk_files,
+                env.disk_links.as_ref(),
+            )
+            .await;
+        }
+    } else if let Some(trace) = env.test_trace {
+        test_trace(
+            &trace,
+            env.trace_speed,
+            env.trace_start
+                .expect("--test-trace requires --trace-start"),
+            env.disk_allocation,
+            env.test_disk_semantics,
+            &env.disk_layout,
+        )
+        .await;
+    }
+}
+
+/// Without explicitly named threads a single test_thread carries the whole target
+fn threads_or_default(threads: Vec<ThreadLoad>, target: f64) -> Vec<ThreadLoad> {
+    if threads.is_empty() {
+        vec![ThreadLoad {
+            name: "test_thread".to_string(),
+            target,
+        }]
+    } else {
+        threads
     }
 }
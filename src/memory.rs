@@ -0,0 +1,248 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+use std::ptr;
+use std::str::FromStr;
+
+/// How the memory load is allocated, each one shows up differently in /proc/<pid>/status
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MemoryStrategy {
+    /// Zero filled Vec<u8> on the heap (RssAnon)
+    #[default]
+    Heap,
+    /// Private anonymous mmap (RssAnon)
+    AnonymousMmap,
+    /// Shared mmap of a file, the dirty page cache is mapped into the process (RssFile)
+    FileMmap,
+    /// Shared mmap of a file in /dev/shm (RssShmem)
+    SharedMemory,
+}
+
+impl FromStr for MemoryStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "heap" => Ok(MemoryStrategy::Heap),
+            "anonymous-mmap" => Ok(MemoryStrategy::AnonymousMmap),
+            "file-mmap" => Ok(MemoryStrategy::FileMmap),
+            "shared-memory" => Ok(MemoryStrategy::SharedMemory),
+            _ => Err(format!(
+                "Was expecting heap, anonymous-mmap, file-mmap or shared-memory, got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MemoryStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryStrategy::Heap => write!(f, "heap"),
+            MemoryStrategy::AnonymousMmap => write!(f, "anonymous-mmap"),
+            MemoryStrategy::FileMmap => write!(f, "file-mmap"),
+            MemoryStrategy::SharedMemory => write!(f, "shared-memory"),
+        }
+    }
+}
+
+/// Which memory of a process the monitoring is expected to report, the strategies of the memory
+/// load differ in the pages they make resident
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MemorySemantics {
+    /// Every resident page (VmRSS), whatever the strategy allocating it
+    #[default]
+    Resident,
+    /// Resident anonymous pages only (RssAnon), file backed and shared mappings are left out
+    Anonymous,
+}
+
+impl MemorySemantics {
+    /// Returns the memory expected for `size` bytes allocated with the `strategy`
+    pub fn expected_size(&self, size: u64, strategy: MemoryStrategy) -> u64 {
+        match (self, strategy) {
+            (
+                MemorySemantics::Anonymous,
+                MemoryStrategy::FileMmap | MemoryStrategy::SharedMemory,
+            ) => 0,
+            _ => size,
+        }
+    }
+}
+
+impl FromStr for MemorySemantics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resident" => Ok(MemorySemantics::Resident),
+            "anonymous" => Ok(MemorySemantics::Anonymous),
+            _ => Err(format!("Was expecting resident or anonymous, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for MemorySemantics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemorySemantics::Resident => write!(f, "resident"),
+            MemorySemantics::Anonymous => write!(f, "anonymous"),
+        }
+    }
+}
+
+/// Resident memory allocated with one of the [MemoryStrategy]s, released on drop
+pub enum MemoryBlock {
+    Heap(Vec<u8>),
+    /// A mapping of `len` bytes, backed by the unlinked `file` for the file strategies
+    Mapped {
+        ptr: *mut libc::c_void,
+        len: usize,
+        file: Option<File>,
+    },
+}
+
+impl MemoryBlock {
+    /// Allocates `len` bytes and writes every page so the whole block becomes resident
+    pub fn allocate(strategy: MemoryStrategy, len: usize) -> Self {
+        match strategy {
+            MemoryStrategy::Heap => {
+                // vec![0; len] would be backed by untouched zero pages, resize writes every page so it becomes resident
+                #[allow(clippy::slow_vector_initialization)]
+                let mut memory: Vec<u8> = Vec::with_capacity(len);
+                memory.resize(len, 0);
+                MemoryBlock::Heap(memory)
+            }
+            MemoryStrategy::AnonymousMmap => {
+                Self::map(len, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, None)
+            }
+            MemoryStrategy::FileMmap => Self::map_file(Path::new("."), len),
+            MemoryStrategy::SharedMemory => Self::map_file(Path::new("/dev/shm"), len),
+        }
+    }
+
+    /// Maps a file unique to this process in `dir`, the file is unlinked right away so nothing is
+    /// left behind, the mapping keeps the pages alive
+    fn map_file(dir: &Path, len: usize) -> Self {
+        let path = dir.join(format!("monitoring-test-memory-{}", process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap_or_else(|_| panic!("Cannot create memory file: {:?}", &path));
+        file.set_len(len as u64)
+            .unwrap_or_else(|_| panic!("Cannot set memory file length: {:?}", &path));
+
+        fs::remove_file(&path).unwrap_or_else(|_| panic!("Cannot remove memory file: {:?}", &path));
+        Self::map(len, libc::MAP_SHARED, Some(file))
+    }
+
+    fn map(len: usize, flags: libc::c_int, file: Option<File>) -> Self {
+        if len == 0 {
+            return MemoryBlock::Heap(Vec::new());
+        }
+        // SAFETY: a fresh mapping not aliasing any existing memory, checked for MAP_FAILED
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags,
+                file.as_ref().map_or(-1, |file| file.as_raw_fd()),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            panic!("Cannot mmap {} bytes", len);
+        }
+        // SAFETY: the mapping is `len` bytes long and writable
+        unsafe { ptr::write_bytes(ptr as *mut u8, 1, len) };
+        MemoryBlock::Mapped { ptr, len, file }
+    }
+
+    /// Grows the block by `additional` bytes and writes them, a mapping is remapped so it stays a
+    /// single mapping of whole pages however small the steps
+    pub fn grow(&mut self, additional: usize) {
+        match self {
+            MemoryBlock::Heap(memory) => memory.resize(memory.len() + additional, 0),
+            MemoryBlock::Mapped { ptr, len, file } => {
+                let new_len = *len + additional;
+                if let Some(file) = file {
+                    file.set_len(new_len as u64)
+                        .unwrap_or_else(|_| panic!("Cannot grow memory file to {} bytes", new_len));
+                }
+                // SAFETY: the mapping was created by `map`, nothing else points into it
+                let new_ptr = unsafe { libc::mremap(*ptr, *len, new_len, libc::MREMAP_MAYMOVE) };
+                if new_ptr == libc::MAP_FAILED {
+                    panic!("Cannot grow mapping to {} bytes", new_len);
+                }
+                // SAFETY: the mapping is `new_len` bytes long and writable
+                unsafe { ptr::write_bytes((new_ptr as *mut u8).add(*len), 1, additional) };
+                *ptr = new_ptr;
+                *len = new_len;
+            }
+        }
+    }
+}
+
+impl Drop for MemoryBlock {
+    fn drop(&mut self) {
+        if let MemoryBlock::Mapped { ptr, len, .. } = *self {
+            // SAFETY: the mapping was created by `map` and is not referenced anywhere else
+            unsafe { libc::munmap(ptr, len) };
+        }
+    }
+}
+
+extern "C" {
+    // glibc only, not exposed by the libc crate
+    fn malloc_trim(pad: libc::size_t) -> libc::c_int;
+}
+
+/// Freed heap chunks can stay in the allocator's arenas, gives them back to the OS
+pub fn release_free_heap() {
+    // SAFETY: malloc_trim only releases memory that is already free
+    unsafe { malloc_trim(0) };
+}
+
+/// What the memory growth does once the ceiling is reached
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GrowthMode {
+    /// Keeps the memory at the ceiling
+    Hold,
+    /// Releases all the memory and starts growing again
+    Repeat,
+}
+
+impl FromStr for GrowthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hold" => Ok(GrowthMode::Hold),
+            "repeat" => Ok(GrowthMode::Repeat),
+            _ => Err(format!("Was expecting hold or repeat, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for GrowthMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrowthMode::Hold => write!(f, "hold"),
+            GrowthMode::Repeat => write!(f, "repeat"),
+        }
+    }
+}
+
+/// Steady growth of resident memory, simulating a slow leak
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryGrowth {
+    /// Growth rate in bytes per second
+    pub rate: usize,
+    pub mode: GrowthMode,
+}
@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::quantity;
+
+/// Shape of a load over time, scales the load target by a factor in the interval [0, 1]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LoadProfile {
+    /// The full target for the whole run
+    #[default]
+    Constant,
+    /// Linear ramp from 0 to the target over the duration, holds the target afterwards
+    Ramp(Duration),
+    /// Staircase of `steps` equal steps up to the target, each held for the interval, then repeats
+    Step { steps: u32, interval: Duration },
+    /// Sine wave between 0 and the target with the period, starting at 0
+    Sine(Duration),
+    /// Square wave, the target for the first half of the period and 0 for the second half
+    Square(Duration),
+}
+
+impl LoadProfile {
+    /// Returns the fraction of the target to generate `elapsed` time after the profile start
+    pub fn fraction(&self, elapsed: Duration) -> f64 {
+        match *self {
+            LoadProfile::Constant => 1.0,
+            LoadProfile::Ramp(duration) => {
+                (elapsed.as_secs_f64() / duration.as_secs_f64()).min(1.0)
+            }
+            LoadProfile::Step { steps, interval } => {
+                let step = (elapsed.as_secs_f64() / interval.as_secs_f64()) as u64 % steps as u64;
+                (step + 1) as f64 / steps as f64
+            }
+            LoadProfile::Sine(period) => {
+                let phase = elapsed.as_secs_f64() / period.as_secs_f64();
+                0.5 - 0.5 * (2.0 * PI * phase).cos()
+            }
+            LoadProfile::Square(period) => {
+                let phase = elapsed.as_secs_f64() / period.as_secs_f64();
+                if phase.fract() < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for LoadProfile {
+    type Err = String;
+
+    /// Parses `constant`, `ramp:DURATION`, `step:STEPS:DURATION`, `sine:DURATION` or
+    /// `square:DURATION`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+
+        match parts.as_slice() {
+            ["constant"] => Ok(LoadProfile::Constant),
+            ["ramp", duration] => Ok(LoadProfile::Ramp(quantity::positive_duration(duration)?)),
+            ["step", steps, interval] => Ok(LoadProfile::Step {
+                steps: steps
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|steps| *steps > 0)
+                    .ok_or_else(|| format!("Was expecting positive STEPS, got {}", steps))?,
+                interval: quantity::positive_duration(interval)?,
+            }),
+            ["sine", period] => Ok(LoadProfile::Sine(quantity::positive_duration(period)?)),
+            ["square", period] => Ok(LoadProfile::Square(quantity::positive_duration(period)?)),
+            _ => Err(format!(
+                "Was expecting constant, ramp:DURATION, step:STEPS:DURATION, sine:DURATION or square:DURATION, got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LoadProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadProfile::Constant => write!(f, "constant"),
+            LoadProfile::Ramp(duration) => write!(f, "ramp:{}s", duration.as_secs_f64()),
+            LoadProfile::Step { steps, interval } => {
+                write!(f, "step:{}:{}s", steps, interval.as_secs_f64())
+            }
+            LoadProfile::Sine(period) => write!(f, "sine:{}s", period.as_secs_f64()),
+            LoadProfile::Square(period) => write!(f, "square:{}s", period.as_secs_f64()),
+        }
+    }
+}
+
+/// Returns the time elapsed since `start`, 0 if `start` is in the future
+pub fn elapsed_since(start: SystemTime) -> Duration {
+    SystemTime::now().duration_since(start).unwrap_or_default()
+}
+
+/// Converts the profile start to unix time in milliseconds, to hand it over to a subprocess
+pub fn to_unix_millis(start: SystemTime) -> u128 {
+    start
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// Converts unix time in milliseconds back to the profile start
+pub fn from_unix_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+/// Splits `value` into the leading number and the unit following it
+fn split_unit(value: &str) -> Result<(f64, String), String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("Was expecting a number followed by a unit, got {}", value))?;
+    Ok((number, unit.trim().to_ascii_lowercase()))
+}
+
+/// Parses a size in bytes, e.g. `2GiB`, `512MB` or `1073741824`
+///
+/// KB, MB, GB and TB are decimal units, KiB, MiB, GiB and TiB (or just K, M, G and T) are binary
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value)?;
+    let multiplier: u64 = match unit.as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "Unknown size unit {} in {}, was expecting B, KB, MB, GB, TB, KiB, MiB, GiB or TiB",
+                unit, value
+            ))
+        }
+    };
+    let size = (number * multiplier as f64).round();
+    // a float cast saturates, a size past u64::MAX would silently turn into u64::MAX
+    if size >= u64::MAX as f64 {
+        return Err(format!("Size {} does not fit in 64 bits", value));
+    }
+    Ok(size as u64)
+}
+
+/// Parses a rate in bytes per second, e.g. `300KiB/s`, `1MB` or `1048576`
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let size = value.trim();
+    let size = size
+        .strip_suffix("/s")
+        .or_else(|| size.strip_suffix("/S"))
+        .unwrap_or(size);
+    parse_size(size)
+}
+
+/// Parses a duration, e.g. `90s`, `5m`, `500ms` or `30`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = split_unit(value)?;
+    let secs = match unit.as_str() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" | "min" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => {
+            return Err(format!(
+                "Unknown duration unit {} in {}, was expecting ms, s, m or h",
+                unit, value
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("Duration {} is too long", value))
+}
+
+/// Parses a duration longer than zero
+pub fn positive_duration(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        duration if duration.is_zero() => {
+            Err(format!("Was expecting a positive duration, got {}", value))
+        }
+        duration => Ok(duration),
+    }
+}
+
+/// Parses a percentage, e.g. `75%` or `75`
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let (number, unit) = split_unit(value)?;
+    match unit.as_str() {
+        "" | "%" => Ok(number),
+        _ => Err(format!("Was expecting a percentage, got {}", value)),
+    }
+}
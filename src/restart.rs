@@ -0,0 +1,88 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::quantity;
+
+/// When a protocol-runner subprocess is killed and respawned, simulating a crashing runner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartSchedule {
+    /// Restarts after every interval
+    Every(Duration),
+    /// Restarts after a random interval between `min` and `max`
+    Random { min: Duration, max: Duration },
+}
+
+impl RestartSchedule {
+    /// Returns the endless sequence of intervals between two restarts
+    pub fn delays(self) -> Delays {
+        // seeded from the clock, every run restarts at different times
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_nanos() as u64;
+        Delays {
+            schedule: self,
+            state: seed | 1,
+        }
+    }
+}
+
+/// Intervals between two restarts of a [RestartSchedule]
+pub struct Delays {
+    schedule: RestartSchedule,
+    /// xorshift state, never 0
+    state: u64,
+}
+
+impl Iterator for Delays {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.schedule {
+            RestartSchedule::Every(interval) => Some(interval),
+            RestartSchedule::Random { min, max } => {
+                self.state ^= self.state << 13;
+                self.state ^= self.state >> 7;
+                self.state ^= self.state << 17;
+                let fraction = (self.state >> 11) as f64 / (1u64 << 53) as f64;
+                Some(min + (max - min).mul_f64(fraction))
+            }
+        }
+    }
+}
+
+impl FromStr for RestartSchedule {
+    type Err = String;
+
+    /// Parses `DURATION` or `random:MIN-MAX`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("random:") {
+            Some(range) => {
+                let (min, max) = range
+                    .split_once('-')
+                    .ok_or_else(|| format!("Was expecting random:MIN-MAX, got {}", s))?;
+                let (min, max) = (
+                    quantity::positive_duration(min)?,
+                    quantity::positive_duration(max)?,
+                );
+                if min > max {
+                    return Err(format!("Was expecting MIN up to MAX, got {}", s));
+                }
+                Ok(RestartSchedule::Random { min, max })
+            }
+            None => Ok(RestartSchedule::Every(quantity::positive_duration(s)?)),
+        }
+    }
+}
+
+impl fmt::Display for RestartSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartSchedule::Every(interval) => write!(f, "{}ms", interval.as_millis()),
+            RestartSchedule::Random { min, max } => {
+                write!(f, "random:{}ms-{}ms", min.as_millis(), max.as_millis())
+            }
+        }
+    }
+}
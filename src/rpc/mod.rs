@@ -1,6 +1,10 @@
+use std::env;
+
 use tokio::task::JoinHandle;
 
 pub mod filters;
+pub mod sink;
+pub mod source;
 
 pub const MEASUREMENTS_MAX_CAPACITY: usize = 40320;
 
@@ -12,3 +16,12 @@ pub fn spawn_rpc_server(rpc_port: u16) -> JoinHandle<()> {
         warp::serve(api).run(([0, 0, 0, 0], rpc_port)).await;
     })
 }
+
+/// Spawns the rpc server on the port in the RPC_PORT environment variable, 18732 by default
+pub fn spawn_rpc_server_from_env() -> JoinHandle<()> {
+    let port = env::var("RPC_PORT")
+        .unwrap_or_else(|_| "18732".to_string())
+        .parse::<u16>()
+        .expect("Expected u16");
+    spawn_rpc_server(port)
+}
@@ -0,0 +1,37 @@
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+/// Size of the buffer the received data is discarded into
+const DATA_SINK_BUFFER_SIZE: usize = 65_536;
+
+/// Spawns a local TCP server on an ephemeral loopback port, discarding everything sent to it, so the
+/// network send load does not depend on an external host
+///
+/// The server runs in the node itself, the node receives every byte it sends over the loopback
+/// interface, so the sent bytes cannot be told apart from the received ones
+pub fn spawn_data_sink() -> SocketAddr {
+    let listener = StdTcpListener::bind(("127.0.0.1", 0)).expect("Cannot bind data sink");
+    let addr = listener.local_addr().expect("Cannot get data sink address");
+    listener
+        .set_nonblocking(true)
+        .expect("Cannot set data sink to non-blocking");
+
+    tokio::spawn(async move {
+        let listener = TcpListener::from_std(listener).expect("Cannot create data sink listener");
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = vec![0u8; DATA_SINK_BUFFER_SIZE];
+                // read until the sender hangs up
+                while let Ok(len) = stream.read(&mut buffer).await {
+                    if len == 0 {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    addr
+}
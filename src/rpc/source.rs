@@ -0,0 +1,44 @@
+use std::net::SocketAddr;
+
+use tokio::time::{interval, Duration};
+use warp::hyper::body::{Body, Bytes};
+use warp::reply::Response;
+use warp::Filter;
+
+/// Interval between two chunks sent by the data source
+const DATA_SOURCE_TICK: Duration = Duration::from_millis(100);
+
+/// Spawns a local HTTP server on an ephemeral loopback port, streaming generated bytes at the
+/// `rate` in bytes per second on `GET /data`, so the network load does not depend on an external host
+///
+/// The server runs in the node itself, the node sends every byte it receives over the loopback
+/// interface, so the received bytes cannot be told apart from the sent ones
+pub fn spawn_data_source(rate: u64) -> SocketAddr {
+    let data = warp::path!("data")
+        .and(warp::get())
+        .map(move || stream_data(rate));
+
+    let (addr, server) = warp::serve(data).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    addr
+}
+
+/// Streams an endless body, sending one chunk of `rate` / ticks per second on every tick
+fn stream_data(rate: u64) -> Response {
+    let chunk_len = (rate as f64 * DATA_SOURCE_TICK.as_secs_f64()).ceil() as usize;
+    let chunk = Bytes::from((0..chunk_len).map(|i| (i % 251) as u8).collect::<Vec<u8>>());
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut ticks = interval(DATA_SOURCE_TICK);
+        loop {
+            ticks.tick().await;
+            // the client hung up
+            if sender.send_data(chunk.clone()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Response::new(body)
+}
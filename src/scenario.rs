@@ -0,0 +1,266 @@
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::configuration::{self, ThreadLoad};
+use crate::quantity;
+
+/// Several loads running at the same time, each one started and stopped on its own schedule
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    pub name: String,
+    pub loads: Vec<ScheduledLoad>,
+}
+
+/// A load of a scenario, generated by the `role` from `start` after the scenario start for the
+/// `duration`, or for as long as the node runs
+#[derive(Clone, Debug)]
+pub struct ScheduledLoad {
+    pub load: ScenarioLoad,
+    pub role: Role,
+    pub start: Duration,
+    pub duration: Option<Duration>,
+}
+
+/// Process generating a load of a scenario
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// The node itself, the load runs on threads of the node
+    Node,
+    /// A protocol-runner subprocess of the node
+    Subprocess,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "node" => Ok(Role::Node),
+            "subprocess" => Ok(Role::Subprocess),
+            _ => Err(format!("Was expecting node or subprocess, got {}", s)),
+        }
+    }
+}
+
+/// Kind and target of a load of a scenario
+#[derive(Clone, Debug)]
+pub enum ScenarioLoad {
+    /// Named threads with their cpu load in percent of one CPU
+    Cpu(Vec<ThreadLoad>),
+    /// Memory in bytes
+    Memory(usize),
+    /// Bytes written per second
+    IoWrite(u64),
+    /// Bytes read per second
+    IoRead(u64),
+    /// Bytes sent per second
+    NetworkSend(u64),
+    /// Open file descriptors
+    Fds(usize),
+}
+
+impl ScenarioLoad {
+    /// Returns the arguments running the load in a subprocess
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        match self {
+            ScenarioLoad::Cpu(threads) => {
+                let target: f64 = threads.iter().map(|thread| thread.target).sum();
+                args.extend(["--cpu-load".to_string(), target.to_string()]);
+                for thread in threads {
+                    args.extend([
+                        "--cpu-load-thread".to_string(),
+                        format!("{}={}", thread.name, thread.target),
+                    ]);
+                }
+            }
+            ScenarioLoad::Memory(size) => {
+                args.extend(["--memory-load".to_string(), size.to_string()])
+            }
+            ScenarioLoad::IoWrite(rate) => {
+                args.extend(["--io-write-load".to_string(), rate.to_string()])
+            }
+            ScenarioLoad::IoRead(rate) => {
+                args.extend(["--io-read-load".to_string(), rate.to_string()])
+            }
+            ScenarioLoad::NetworkSend(rate) => {
+                args.extend(["--network-send-load".to_string(), rate.to_string()])
+            }
+            ScenarioLoad::Fds(count) => args.extend(["--fd-load".to_string(), count.to_string()]),
+        }
+        args
+    }
+}
+
+/// A `[[load]]` table of a scenario file, as written
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoadEntry {
+    kind: String,
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    target: Option<toml::Value>,
+    #[serde(default)]
+    threads: Vec<String>,
+    #[serde(default)]
+    start: Option<String>,
+    #[serde(default)]
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    load: Vec<LoadEntry>,
+}
+
+impl LoadEntry {
+    fn parse(self) -> Result<ScheduledLoad, String> {
+        // a target is a quantity string, or a plain number
+        let value = self.target.as_ref().map(|target| match target.as_str() {
+            Some(target) => target.to_string(),
+            None => target.to_string(),
+        });
+        let target = || {
+            value
+                .as_deref()
+                .ok_or_else(|| format!("The {} load has no target", self.kind))
+        };
+        if !self.threads.is_empty() && self.kind != "cpu" {
+            return Err(format!(
+                "Only a cpu load has threads, got a {} load",
+                self.kind
+            ));
+        }
+
+        let load = match self.kind.as_str() {
+            "cpu" if self.threads.is_empty() => {
+                // without threads a single test_thread carries the whole target, like --cpu-load
+                ScenarioLoad::Cpu(vec![ThreadLoad {
+                    name: "test_thread".to_string(),
+                    target: configuration::thread_percent(target()?)?,
+                }])
+            }
+            "cpu" if value.is_some() => {
+                return Err("The cpu load has either a target or threads".to_string())
+            }
+            "cpu" => ScenarioLoad::Cpu(
+                self.threads
+                    .iter()
+                    .map(|thread| thread.parse())
+                    .collect::<Result<_, _>>()?,
+            ),
+            "memory" => {
+                ScenarioLoad::Memory(positive(configuration::memory_size(target()?)? as u64)? as usize)
+            }
+            "io-write" => ScenarioLoad::IoWrite(positive(quantity::parse_rate(target()?)?)?),
+            "io-read" => ScenarioLoad::IoRead(positive(quantity::parse_rate(target()?)?)?),
+            "network-send" => {
+                ScenarioLoad::NetworkSend(positive(quantity::parse_rate(target()?)?)?)
+            }
+            "fds" => {
+                let count = target()?;
+                ScenarioLoad::Fds(
+                    count
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| format!("Was expecting a positive count, got {}", count))?,
+                )
+            }
+            kind => return Err(format!(
+                "Unknown load kind {}, was expecting cpu, memory, io-write, io-read, network-send or fds",
+                kind
+            )),
+        };
+
+        Ok(ScheduledLoad {
+            load,
+            role: self.role.as_deref().unwrap_or("node").parse()?,
+            start: self
+                .start
+                .as_deref()
+                .map(quantity::parse_duration)
+                .transpose()?
+                .unwrap_or_default(),
+            duration: self
+                .duration
+                .as_deref()
+                .map(quantity::positive_duration)
+                .transpose()?,
+        })
+    }
+}
+
+fn positive(value: u64) -> Result<u64, String> {
+    match value {
+        0 => Err("Was expecting a positive target, got 0".to_string()),
+        value => Ok(value),
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    /// Reads a scenario from a toml file with a `[[load]]` table for every load
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let content =
+            fs::read_to_string(s).map_err(|e| format!("Cannot read scenario file {}: {}", s, e))?;
+        let file: ScenarioFile =
+            toml::from_str(&content).map_err(|e| format!("Invalid scenario file {}: {}", s, e))?;
+
+        if file.load.is_empty() {
+            return Err(format!("Scenario file {} has no loads", s));
+        }
+        let loads: Vec<ScheduledLoad> = file
+            .load
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                entry
+                    .parse()
+                    .map_err(|e| format!("Load {} of {}: {}", index + 1, s, e))
+            })
+            .collect::<Result<_, String>>()?;
+        check_thread_names(&loads, s)?;
+
+        Ok(Self {
+            name: s.to_string(),
+            loads,
+        })
+    }
+}
+
+/// The cpu loads of the node run their threads in the same process, and the monitoring reports a
+/// thread by its name, so a name is used once across all of them
+fn check_thread_names(loads: &[ScheduledLoad], file: &str) -> Result<(), String> {
+    let mut node_threads: Vec<(&str, usize)> = Vec::new();
+    for (index, load) in loads.iter().enumerate() {
+        if let ScenarioLoad::Cpu(threads) = &load.load {
+            configuration::check_unique_names(threads)
+                .map_err(|e| format!("Load {} of {}: {}", index + 1, file, e))?;
+            if load.role != Role::Node {
+                continue;
+            }
+            for thread in threads {
+                let other = node_threads.iter().find(|(name, _)| *name == thread.name);
+                if let Some((_, other)) = other {
+                    // a cpu load without threads runs on test_thread
+                    return Err(format!(
+                        "Load {} of {}: thread {} is already run by load {}, name the threads of the node cpu loads apart",
+                        index + 1,
+                        file,
+                        thread.name,
+                        other + 1
+                    ));
+                }
+            }
+            node_threads.extend(threads.iter().map(|thread| (thread.name.as_str(), index)));
+        }
+    }
+    Ok(())
+}
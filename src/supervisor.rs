@@ -0,0 +1,123 @@
+use std::env;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Child, Command};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{interval, sleep, Duration, Instant};
+
+/// Interval between two checks for exited subprocesses
+const REAP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time the subprocesses get to exit after a forwarded signal before they are killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    /// Every running subprocess spawned by the simulator
+    static ref CHILDREN: Mutex<Vec<Child>> = Mutex::new(Vec::new());
+}
+
+/// Returns a command running this executable again, the subprocess it spawns is killed when the
+/// thread spawning it dies
+pub fn command() -> Command {
+    let exe = env::current_exe().expect("Cannot resolve the current executable");
+    let mut command = Command::new(exe);
+    // SAFETY: getpid, prctl and getppid are async-signal-safe and only read or change the state of
+    // the calling process
+    unsafe {
+        let parent = libc::getpid();
+        command.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // the parent may have died before the death signal was set
+            if libc::getppid() != parent {
+                return Err(io::Error::from_raw_os_error(libc::ESRCH));
+            }
+            Ok(())
+        });
+    }
+    command
+}
+
+/// Spawns the `command` as a supervised subprocess and returns its pid, the subprocess is reaped
+/// once it exits
+pub fn spawn(command: &mut Command) -> u32 {
+    let child = command.spawn().expect("Cannot run subprocess");
+    let pid = child.id();
+    CHILDREN.lock().expect("Poisoned subprocesses").push(child);
+    pid
+}
+
+/// Kills the subprocess with the `pid` and reaps it
+pub fn kill(pid: u32) {
+    let child = {
+        let mut children = CHILDREN.lock().expect("Poisoned subprocesses");
+        children
+            .iter()
+            .position(|child| child.id() == pid)
+            .map(|index| children.remove(index))
+    };
+    // the subprocess exited and was reaped already
+    if let Some(mut child) = child {
+        child.kill().expect("Cannot kill subprocess");
+        child.wait().expect("Cannot reap subprocess");
+    }
+}
+
+/// Reaps the exited subprocesses in the background, and forwards SIGTERM and SIGINT to every
+/// subprocess and waits for them before exiting, so no subprocess outlives the simulator
+pub fn start() {
+    tokio::spawn(async {
+        let mut ticks = interval(REAP_INTERVAL);
+        loop {
+            ticks.tick().await;
+            reap();
+        }
+    });
+
+    tokio::spawn(async {
+        let mut terminate = signal(SignalKind::terminate()).expect("Cannot handle SIGTERM");
+        let mut interrupt = signal(SignalKind::interrupt()).expect("Cannot handle SIGINT");
+        let signo = tokio::select! {
+            _ = terminate.recv() => libc::SIGTERM,
+            _ = interrupt.recv() => libc::SIGINT,
+        };
+        shutdown(signo).await;
+        process::exit(128 + signo);
+    });
+}
+
+/// Drops the subprocesses that exited
+fn reap() {
+    CHILDREN
+        .lock()
+        .expect("Poisoned subprocesses")
+        .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+}
+
+/// Forwards the signal `signo` to every subprocess, kills the ones still running after the
+/// [SHUTDOWN_TIMEOUT]
+async fn shutdown(signo: libc::c_int) {
+    for child in CHILDREN.lock().expect("Poisoned subprocesses").iter() {
+        // SAFETY: the pid belongs to a child that was not reaped yet, so it was not reused
+        unsafe { libc::kill(child.id() as libc::pid_t, signo) };
+    }
+
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while Instant::now() < deadline {
+        reap();
+        if CHILDREN.lock().expect("Poisoned subprocesses").is_empty() {
+            return;
+        }
+        sleep(REAP_INTERVAL).await;
+    }
+
+    for mut child in CHILDREN.lock().expect("Poisoned subprocesses").drain(..) {
+        println!("\tKILLING SUBPROCESS {}", child.id());
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
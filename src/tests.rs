@@ -1,26 +1,111 @@
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use tokio::time::{sleep, Duration};
 
-pub async fn test_cpu(target: f64) {
-    println!("=== TESTING NODE CPU MEASUREMENTS ===\n\n");
+use crate::configuration::{Churn, ThreadLoad};
+use crate::disk::{
+    DiskAllocation, DiskFiles, DiskGrowth, DiskLayout, DiskLinks, DiskShrinkMode, DiskSizeSemantics,
+};
+use crate::loads::P2pOptions;
+use crate::memory::{MemorySemantics, MemoryStrategy};
+use crate::profile::{self, LoadProfile};
+use crate::trace::Trace;
 
-    let error_margin = 10.0;
+/// Block size of the file system, the allocated size of a file is a multiple of it
+const BLOCK_SIZE: u64 = 4096;
 
-    println!("\tTARGET: {}%", target);
-    println!("\tERROR MARGIN: {}%\n", error_margin);
+/// Churned threads that may be alive on top of the expected ones when the monitoring samples
+const CHURN_THREADS_MARGIN: usize = 2;
+
+/// Fresh measurements waited at most for a protocol-runner restart
+const RESTART_SAMPLES: usize = 120;
+
+/// Interval between two polls for a fresh measurement
+const FRESH_MEASUREMENT_POLL: Duration = Duration::from_millis(500);
+
+/// Waits at most 5 minutes for a fresh measurement
+const FRESH_MEASUREMENT_RETRIES: usize = 600;
+
+/// The monitoring timestamps a measurement in whole seconds, the cpu load it holds was measured
+/// within the second before or after its timestamp
+const CPU_MEASUREMENT_WINDOW: Duration = Duration::from_secs(1);
+
+/// Points of the measurement window the cpu profile is averaged over
+const CPU_PROFILE_POINTS: u32 = 20;
+
+/// Time the loads and the monitoring get to follow a new sample of a replayed trace, the
+/// measurements sampled within it are not compared
+const TRACE_SETTLE: Duration = Duration::from_secs(5);
+
+/// Time the disk growth is followed on top of the shrink interval, so at least one shrink is seen
+const DISK_GROWTH_WINDOW: Duration = Duration::from_secs(10);
+
+/// There could be many bottlenecks to networking, 100 KB/s
+const NETWORK_ERROR_MARGIN: u64 = 102_400;
+
+/// Loopback frame, ip and tcp headers of a segment (14 + 20 + 32 bytes), a message may take a
+/// segment of its own and an ack
+const TCP_OVERHEAD: u64 = 2 * 66;
+
+/// The targets are scaled by the `profile` started at `profile_start`, evaluated when the
+/// measurement was sampled, so a monitoring reporting a steady state fails a varying load
+pub async fn test_cpu(
+    target: f64,
+    threads: &[ThreadLoad],
+    error_margin: f64,
+    profile: LoadProfile,
+    profile_start: SystemTime,
+) {
+    println!("=== TESTING NODE CPU MEASUREMENTS ===\n\n");
 
     let res = get_latest_measurement(Duration::from_secs(0)).await;
 
+    let fraction = match profile {
+        LoadProfile::Constant => 1.0,
+        profile => {
+            let timestamp = res[0]["timestamp"]
+                .as_u64()
+                .unwrap_or_else(|| panic!("Test failed: No timestamp found in measurements"));
+            let fraction = profile_fraction(profile, profile_start, timestamp);
+            println!(
+                "\t{} PROFILE AT {}: {:.1}% OF THE TARGETS",
+                profile,
+                timestamp,
+                fraction * 100.0
+            );
+            fraction
+        }
+    };
+    let target = target * fraction;
+    let threads: Vec<ThreadLoad> = threads
+        .iter()
+        .map(|thread| ThreadLoad {
+            name: thread.name.clone(),
+            target: thread.target * fraction,
+        })
+        .collect();
+
+    // every thread can deviate by the error margin
+    let collective_target: f64 = threads.iter().map(|thread| thread.target).sum();
+    let collective_error_margin = error_margin * threads.len() as f64;
+
+    println!("\tTARGET: {}%", collective_target);
+    println!("\tERROR MARGIN: {}%\n", collective_error_margin);
+
     if let Some(cpu_data) = res[0]["cpu"]["node"]["collective"].as_f64() {
         println!("\tCOLLECTIVE CPU at: {}%\n", cpu_data);
         // Make sure the measurement is withing the defined interval (with the error margin)
-        assert!(target + error_margin >= cpu_data);
-        assert!(target - error_margin <= cpu_data);
+        assert!(collective_target + collective_error_margin >= cpu_data);
+        assert!(collective_target - collective_error_margin <= cpu_data);
 
         println!("=== OK ===\n");
     } else {
         panic!("Test failed: No cpu data found in measurements")
     }
 
+    // the subprocess runs a single test_thread with the base target
+    println!("\tSUBPROCESS TARGET: {}%\n", target);
     if let Some(subprocess) = res[0]["cpu"]["validators"]["validators"].as_object() {
         if let Some(key) = subprocess
             .keys().find(|key| key.contains("protocol-runner"))
@@ -41,32 +126,199 @@ pub async fn test_cpu(target: f64) {
     }
 
     if let Some(tasks) = res[0]["cpu"]["node"]["taskThreads"].as_object() {
-        if let Some(key) = tasks
-            .keys().find(|key| key.contains("test_thread"))
-        {
-            if let Some(thread_cpu) = tasks.get(key).unwrap().as_f64() {
-                println!("\tTHREAD CPU at: {}%\n", thread_cpu);
-                // Make sure the measurement is withing the defined interval (with the error margin)
-                assert!(target + error_margin >= thread_cpu);
-                assert!(target - error_margin <= thread_cpu);
-
-                println!("=== OK ===\n");
+        for thread in &threads {
+            println!("\t{} TARGET: {}%\n", thread.name, thread.target);
+            // the name is chosen by the user, a substring could match another thread
+            if let Some(thread_cpu) = tasks.get(&thread.name) {
+                if let Some(thread_cpu) = thread_cpu.as_f64() {
+                    println!("\tTHREAD {} CPU at: {}%\n", thread.name, thread_cpu);
+                    // Make sure the measurement is withing the defined interval (with the error margin)
+                    assert!(thread.target + error_margin >= thread_cpu);
+                    assert!(thread.target - error_margin <= thread_cpu);
+
+                    println!("=== OK ===\n");
+                }
+            } else {
+                panic!("No thread named {} found", thread.name)
             }
-        } else {
-            panic!("No thread named test_thread found")
         }
     } else {
         panic!("Test failed: No thread data found in cpu measurements")
     }
 }
 
-pub async fn test_memory(target: u64) {
+/// Returns the fraction of the target the `profile` started at `start` asked for, averaged over
+/// the window of the measurement at `timestamp`
+fn profile_fraction(profile: LoadProfile, start: SystemTime, timestamp: u64) -> f64 {
+    let sampled = UNIX_EPOCH + Duration::from_secs(timestamp);
+    let window_start = sampled - CPU_MEASUREMENT_WINDOW;
+    let window = CPU_MEASUREMENT_WINDOW * 2;
+    let total: f64 = (0..=CPU_PROFILE_POINTS)
+        .map(|point| {
+            let at = window_start + window * point / CPU_PROFILE_POINTS;
+            profile.fraction(at.duration_since(start).unwrap_or_default())
+        })
+        .sum();
+    total / (CPU_PROFILE_POINTS + 1) as f64
+}
+
+pub async fn test_threads(idle: usize, churn: Option<Churn>) {
+    println!("=== TESTING NODE THREAD MEASUREMENTS ===\n\n");
+
+    let error_margin = 10.0;
+
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    let tasks = res[0]["cpu"]["node"]["taskThreads"]
+        .as_object()
+        .unwrap_or_else(|| panic!("Test failed: No thread data found in cpu measurements"));
+
+    println!("\tIDLE THREADS TARGET: {}", idle);
+    let idle_threads = tasks.keys().filter(|key| key.contains("idle-")).count();
+    println!("\tIDLE THREADS at: {}\n", idle_threads);
+    assert_eq!(idle, idle_threads);
+    println!("=== OK ===\n");
+
+    if let Some(churn) = churn {
+        // only the threads alive when sampled are expected, the exited ones must not pile up
+        let live_target =
+            (churn.rate * churn.burn.as_secs_f64()).ceil() as usize + CHURN_THREADS_MARGIN;
+        println!("\tCHURNED THREADS TARGET: at most {}", live_target);
+        let churned_threads = tasks.keys().filter(|key| key.contains("churn-")).count();
+        println!("\tCHURNED THREADS at: {}\n", churned_threads);
+        assert!(churned_threads <= live_target);
+        println!("=== OK ===\n");
+
+        // the cpu time of the threads exiting mid-interval still belongs to the node
+        let target = churn.target();
+        println!("\tCOLLECTIVE TARGET: {}%", target);
+        println!("\tERROR MARGIN: {}%\n", error_margin);
+        if let Some(cpu_data) = res[0]["cpu"]["node"]["collective"].as_f64() {
+            println!("\tCOLLECTIVE CPU at: {}%\n", cpu_data);
+            assert!(target + error_margin >= cpu_data);
+            assert!(target - error_margin <= cpu_data);
+
+            println!("=== OK ===\n");
+        } else {
+            panic!("Test failed: No cpu data found in measurements")
+        }
+    }
+}
+
+pub async fn test_fork_storm(churn: Churn) {
+    println!("=== TESTING NODE CPU UNDER FORK STORM ===\n\n");
+
+    // the exec counts towards the burn of a subprocess, spawning it costs the node some cpu on top
+    let error_margin = 15.0;
+    // the subprocesses exit between two samples, their cpu time is only accounted to the node
+    // once they are reaped
+    let target = churn.target();
+    println!("\tTARGET: {}%", target);
+    println!("\tERROR MARGIN: {}%\n", error_margin);
+
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    if let Some(cpu_data) = res[0]["cpu"]["node"]["collective"].as_f64() {
+        println!("\tCOLLECTIVE CPU at: {}%\n", cpu_data);
+        assert!(target + error_margin >= cpu_data);
+        assert!(target - error_margin <= cpu_data);
+
+        println!("=== OK ===\n");
+    } else {
+        panic!("Test failed: No cpu data found in measurements")
+    }
+}
+
+/// The subprocess measurements are keyed per process, a restarted protocol-runner shows up under
+/// a new key and its old key has to go away
+pub async fn test_subprocess_restart(samples: usize) {
+    println!("=== TESTING SUBPROCESS RESTART ===\n\n");
+    println!("\tSAMPLES TO CATCH UP: {}\n", samples);
+
+    let sections = ["cpu", "memory"];
+    let (res, _) = get_fresh_measurement(SystemTime::now()).await;
+    let stale: Vec<BTreeSet<String>> = sections
+        .iter()
+        .map(|section| protocol_runners(&res, section))
+        .collect();
+    for (section, keys) in sections.iter().zip(&stale) {
+        if keys.is_empty() {
+            panic!("Test failed: No {} subprocess found", section)
+        }
+        println!("\t{} SUBPROCESSES: {:?}", section, keys);
+    }
+
+    // sample at which every section noticed the restart
+    let mut changed_at: Vec<Option<usize>> = vec![None; sections.len()];
+    let mut since = SystemTime::now();
+    for sample in 1..=RESTART_SAMPLES {
+        let (res, _) = get_fresh_measurement(since).await;
+        since = SystemTime::now();
+
+        let mut caught_up = true;
+        for (index, section) in sections.iter().enumerate() {
+            let keys = protocol_runners(&res, section);
+            if keys != stale[index] && changed_at[index].is_none() {
+                println!("\t{} SUBPROCESSES CHANGED: {:?}", section, keys);
+                changed_at[index] = Some(sample);
+            }
+
+            let respawned = !keys.is_empty() && keys.is_disjoint(&stale[index]);
+            match changed_at[index] {
+                Some(changed) if !respawned => {
+                    assert!(
+                        sample - changed < samples,
+                        "Test failed: {} subprocesses {:?} did not catch up with the restart",
+                        section,
+                        keys
+                    );
+                    caught_up = false;
+                }
+                Some(_) => (),
+                None => caught_up = false,
+            }
+        }
+
+        if caught_up {
+            println!("\tNEW SUBPROCESSES PICKED UP AFTER {} SAMPLES\n", sample);
+            println!("=== OK ===\n");
+            return;
+        }
+    }
+    panic!(
+        "Test failed: No subprocess restart seen in {} samples",
+        RESTART_SAMPLES
+    )
+}
+
+/// Returns the protocol-runner keys in the subprocess measurements of the `section`
+fn protocol_runners(res: &serde_json::Value, section: &str) -> BTreeSet<String> {
+    res[0][section]["validators"]["validators"]
+        .as_object()
+        .map(|subprocesses| {
+            subprocesses
+                .keys()
+                .filter(|key| key.contains("protocol-runner"))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub async fn test_memory(target: u64, strategy: MemoryStrategy, semantics: MemorySemantics) {
     println!("=== TESTING NODE MEMORY MEASUREMENT ===\n\n");
 
     // we need to take into consideration all the other memory allocation (stacks, warp server...)
     // 30 MB
     let error_margin = 31_457_280;
 
+    // the monitoring may count every resident page or the anonymous ones only, a file backed or
+    // shared mapping is resident but not anonymous
+    println!(
+        "\tMEMORY ALLOCATED AS: {}, EXPECTING {} MEMORY",
+        strategy, semantics
+    );
+    let target = semantics.expected_size(target, strategy);
     println!("\tTARGET: {}MB", bytes_to_megabytes(target));
     println!("\tERROR MARGIN: {}MB\n", bytes_to_megabytes(error_margin));
 
@@ -75,7 +327,7 @@ pub async fn test_memory(target: u64) {
     if let Some(memory_data) = res[0]["memory"]["node"].as_u64() {
         println!("\tMemory at: {}MB\n", bytes_to_megabytes(memory_data));
         assert!(target + error_margin >= memory_data);
-        assert!(target - error_margin <= memory_data);
+        assert!(target.saturating_sub(error_margin) <= memory_data);
 
         println!("=== OK ===\n");
     } else {
@@ -90,7 +342,7 @@ pub async fn test_memory(target: u64) {
                 println!("\tSUBPROCESS MEMORY at: {}MB\n", bytes_to_megabytes(subprocess_memory));
                 // Make sure the measurement is withing the defined interval (with the error margin)
                 assert!(target + error_margin >= subprocess_memory);
-                assert!(target - error_margin <= subprocess_memory);
+                assert!(target.saturating_sub(error_margin) <= subprocess_memory);
 
                 println!("=== OK ===\n");
             }
@@ -102,10 +354,82 @@ pub async fn test_memory(target: u64) {
     }
 }
 
+/// The monitoring may not report fd usage, but it has to keep measuring the processes holding
+/// most of the file descriptors
+pub async fn test_fd_load(samples: usize) {
+    println!("=== TESTING MONITORING UNDER FD LOAD ===\n\n");
+    println!("\tFRESH MEASUREMENTS: {}\n", samples);
+
+    let mut since = SystemTime::now();
+    for sample in 1..=samples {
+        let (res, waited) = get_fresh_measurement(since).await;
+        since = SystemTime::now();
+        println!("\tMEASUREMENT {} AFTER: {:?}", sample, waited);
+
+        if res[0]["memory"]["node"].as_u64().is_none() {
+            panic!("Test failed: No node memory data found in measurements")
+        }
+        if let Some(subprocesses) = res[0]["memory"]["validators"]["validators"].as_object() {
+            if !subprocesses
+                .keys()
+                .any(|key| key.contains("protocol-runner"))
+            {
+                panic!("No subprocess found")
+            }
+        } else {
+            panic!("Test failed: No subprocess memory data found in measurements")
+        }
+
+        println!("=== OK ===\n");
+    }
+}
+
 pub async fn test_network_and_io(target: u64) {
-    println!("=== TESTING NODE IO ===\n\n");
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    assert_io_write(&res, target);
+    assert_network_received(&res, target, NETWORK_ERROR_MARGIN);
+}
+
+pub async fn test_io_write(target: u64) {
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    assert_io_write(&res, target);
+}
+
+pub async fn test_network(target: u64) {
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    assert_network_received(&res, target, NETWORK_ERROR_MARGIN);
+}
+
+pub async fn test_network_sent(target: u64) {
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    assert_network_sent(&res, target, NETWORK_ERROR_MARGIN);
+}
+
+/// The peers and the listener of the p2p load both run in the node, so the node sends and
+/// receives the same bytes over loopback, and the tcp headers and acks of every message on top
+pub async fn test_p2p(options: P2pOptions) {
+    println!("=== TESTING NODE NETWORKING UNDER P2P LOAD ===\n\n");
+    println!(
+        "\t{} PEERS SENDING {} MESSAGES/s OF {} BYTES",
+        options.peers, options.message_rate, options.message_size
+    );
+
+    let target = options.loopback_rate();
+    let error_margin = NETWORK_ERROR_MARGIN + options.loopback_messages() * TCP_OVERHEAD;
+
+    let res = get_latest_measurement(Duration::from_secs(5)).await;
+
+    assert_network_sent(&res, target, error_margin);
+    assert_network_received(&res, target, error_margin);
+}
+
+pub async fn test_io_read(target: u64) {
+    println!("=== TESTING NODE IO READ ===\n\n");
 
-    // there could be many bottlenecks to networking, give it a 20 KB/s error_margin
     // 30 KB/s
     let error_margin = 30_720;
 
@@ -114,19 +438,41 @@ pub async fn test_network_and_io(target: u64) {
 
     let res = get_latest_measurement(Duration::from_secs(5)).await;
 
+    if let Some(io_data) = res[0]["io"]["node"]["readBytesPerSec"].as_u64() {
+        println!("\tDISK READ at: {}KB/s\n", bytes_to_kilobytes(io_data));
+        assert!(target + error_margin >= io_data);
+        assert!(target.saturating_sub(error_margin) <= io_data);
+
+        println!("=== OK ===\n");
+    } else {
+        panic!("Test failed: No io read data found in measurements")
+    }
+}
+
+fn assert_io_write(res: &serde_json::Value, target: u64) {
+    println!("=== TESTING NODE IO ===\n\n");
+
+    // there could be many bottlenecks to networking, give it a 20 KB/s error_margin
+    // 30 KB/s
+    let error_margin = 30_720;
+
+    println!("\tTARGET: {}KB/s", bytes_to_kilobytes(target));
+    println!("\tERROR MARGIN: {}KB/s\n", bytes_to_kilobytes(error_margin));
+
     if let Some(io_data) = res[0]["io"]["node"]["writtenBytesPerSec"].as_u64() {
         println!("\tDISK WRITE at: {}KB/s\n", bytes_to_kilobytes(io_data));
         assert!(target + error_margin >= io_data);
-        assert!(target - error_margin <= io_data);
+        assert!(target.saturating_sub(error_margin) <= io_data);
 
         println!("=== OK ===\n");
     } else {
         panic!("Test failed: No io data found in measurements")
     }
+}
 
+fn assert_network_received(res: &serde_json::Value, target: u64, error_margin: u64) {
     println!("=== TESTING NODE NETWORKING ===\n\n");
 
-    let error_margin = 102_400;
     println!("\tTARGET: {}KB/s", bytes_to_kilobytes(target));
     println!("\tERROR MARGIN: {}KB/s\n", bytes_to_kilobytes(error_margin));
 
@@ -136,7 +482,7 @@ pub async fn test_network_and_io(target: u64) {
             bytes_to_kilobytes(network_data)
         );
         assert!(target + error_margin >= network_data);
-        assert!(target - error_margin <= network_data);
+        assert!(target.saturating_sub(error_margin) <= network_data);
 
         println!("=== OK ===\n");
     } else {
@@ -144,75 +490,471 @@ pub async fn test_network_and_io(target: u64) {
     }
 }
 
-pub async fn test_disk_size(target: u64) {
+fn assert_network_sent(res: &serde_json::Value, target: u64, error_margin: u64) {
+    println!("=== TESTING NODE NETWORKING SENT ===\n\n");
+
+    println!("\tTARGET: {}KB/s", bytes_to_kilobytes(target));
+    println!("\tERROR MARGIN: {}KB/s\n", bytes_to_kilobytes(error_margin));
+
+    if let Some(network_data) = res[0]["network"]["sentBytesPerSec"].as_u64() {
+        println!(
+            "\tNETWORK SENT at: {}KB/s\n",
+            bytes_to_kilobytes(network_data)
+        );
+        assert!(target + error_margin >= network_data);
+        assert!(target.saturating_sub(error_margin) <= network_data);
+
+        println!("=== OK ===\n");
+    } else {
+        panic!("Test failed: No network sent data found in measurements")
+    }
+}
+
+pub async fn test_disk_size(
+    targets: &[u64],
+    allocation: DiskAllocation,
+    semantics: DiskSizeSemantics,
+    layout: &DiskLayout,
+    files: DiskFiles,
+    links: Option<&DiskLinks>,
+) {
     println!("=== TESTING DISK SIZE ===\n\n");
 
     // 30 KB
-    let error_margin = 30_720;
-
-    println!("\tTARGET: {}MB", bytes_to_megabytes(target));
+    let error_margin = match semantics {
+        DiskSizeSemantics::Apparent => 30_720,
+        // the last block of every file is only partly used
+        DiskSizeSemantics::Allocated => 30_720 + files.count as u64 * BLOCK_SIZE,
+    };
+
+    // the monitoring is expected to report the apparent size or the blocks allocated on disk,
+    // a sparse file has its apparent size but (almost) no blocks allocated
+    println!(
+        "\tFILES CREATED AS: {}, EXPECTING {} SIZE",
+        allocation, semantics
+    );
+    println!("\tLAYOUT: {}", layout.name);
+    println!("\tFILES PER DB: {}", files.count);
+    if let Some(links) = links {
+        // the links must not change the reported sizes, see DiskLinks for the counting rules
+        println!("\tLINKED FILES, {} DB SYMLINKED", links.symlinked);
+        if let Some(mounted) = &links.mounted {
+            println!("\tNESTED MOUNT IN {} DB", mounted);
+        }
+    }
     println!("\tERROR MARGIN: {}MB\n", bytes_to_megabytes(error_margin));
 
-    let res = get_latest_measurement(Duration::from_secs(0)).await;
+    // an older measurement could have been sampled before the databases were created
+    // the measurements are timestamped in whole seconds and polled, so the wait is only reported
+    let (res, waited) = get_fresh_measurement(SystemTime::now()).await;
+    println!("\tFRESH MEASUREMENT AFTER: {:?}\n", waited);
 
-    if let Some(diks_data) = res[0]["disk"]["contextStats"].as_u64() {
+    for (database, size) in layout.databases.iter().zip(targets) {
+        // every database has its own target, so swapped databases are caught
+        let target = semantics.expected_size(*size, allocation);
         println!(
-            "\tcontextStats SIZE at: {}MB\n",
-            bytes_to_megabytes(diks_data)
+            "\t{} TARGET: {}MB",
+            database.key,
+            bytes_to_megabytes(target)
         );
-        assert!(target + error_margin >= diks_data);
-        assert!(target - error_margin <= diks_data);
 
-        println!("=== OK ===\n");
-    } else {
-        panic!("Test failed: contextStats data not found in measurements")
+        if let Some(diks_data) = res[0]["disk"][&database.key].as_u64() {
+            println!(
+                "\t{} SIZE at: {}MB\n",
+                database.key,
+                bytes_to_megabytes(diks_data)
+            );
+            let measures = |expected: u64| {
+                expected + error_margin >= diks_data
+                    && expected.saturating_sub(error_margin) <= diks_data
+            };
+
+            if !measures(target) {
+                // name the counting rule the monitoring broke
+                if let Some(links) = links {
+                    let hard_linked =
+                        semantics.expected_size(files.file_size(*size, 0), allocation);
+                    if links.symlinked == database.key && measures(0) {
+                        panic!(
+                            "Test failed: the symlinked {} database directory was skipped",
+                            database.key
+                        )
+                    } else if links.mounted.as_ref() == Some(&database.key) && measures(0) {
+                        // every file of the mounted database is behind its nested mount
+                        panic!(
+                            "Test failed: the nested mount in the {} database directory was skipped",
+                            database.key
+                        )
+                    } else if measures(target + hard_linked) {
+                        panic!(
+                            "Test failed: the hardlinked file in {} was counted twice",
+                            database.key
+                        )
+                    } else if measures(target * 3) {
+                        panic!(
+                            "Test failed: the symlink to a file outside of the databases in {} was followed",
+                            database.key
+                        )
+                    }
+                }
+                panic!(
+                    "Test failed: {} size is not within the error margin",
+                    database.key
+                )
+            }
+
+            println!("=== OK ===\n");
+        } else {
+            panic!(
+                "Test failed: {} data not found in measurements",
+                database.key
+            )
+        }
     }
+}
+
+/// Follows the database sizes over fresh measurements while the disk load grows them, every
+/// size has to grow by the growth rate since the previous measurement, or drop back to the size
+/// left by a shrink and grow from there
+pub async fn test_disk_growth(
+    targets: &[u64],
+    allocation: DiskAllocation,
+    semantics: DiskSizeSemantics,
+    layout: &DiskLayout,
+    files: DiskFiles,
+    growth: DiskGrowth,
+) {
+    println!("=== TESTING DISK GROWTH ===\n\n");
 
-    if let Some(diks_data) = res[0]["disk"]["contextStorage"].as_u64() {
+    // 30 KB
+    let error_margin = match semantics {
+        DiskSizeSemantics::Apparent => 30_720,
+        // the last block of every file is only partly used
+        DiskSizeSemantics::Allocated => 30_720 + files.count as u64 * BLOCK_SIZE,
+    };
+    // the load appends once a second, a measurement may come just before or after an append
+    let rate = semantics.expected_size(growth.rate, allocation);
+    let window = growth.shrink.map_or(DISK_GROWTH_WINDOW, |shrink| {
+        shrink.interval + DISK_GROWTH_WINDOW
+    });
+
+    println!(
+        "\tFILES CREATED AS: {}, EXPECTING {} SIZE",
+        allocation, semantics
+    );
+    println!("\tLAYOUT: {}", layout.name);
+    println!("\tGROWTH: {}KB/s", bytes_to_kilobytes(rate));
+    if let Some(shrink) = growth.shrink {
         println!(
-            "\tcontextStorage SIZE at: {}MB\n",
-            bytes_to_megabytes(diks_data)
+            "\tSHRINKING EVERY {:?} WITH {}",
+            shrink.interval, shrink.mode
         );
-        assert!(target + error_margin >= diks_data);
-        assert!(target - error_margin <= diks_data);
+    }
+    println!("\tFOLLOWED FOR: {:?}", window);
+    println!("\tERROR MARGIN: {}KB\n", bytes_to_kilobytes(error_margin));
+
+    // the size every database is shrunk back to, deleting the growing last file takes its
+    // initial size along
+    let floors: Vec<u64> = targets
+        .iter()
+        .map(|size| {
+            let base = semantics.expected_size(*size, allocation);
+            match growth.shrink {
+                Some(shrink) if shrink.mode == DiskShrinkMode::Delete => {
+                    let last = files.file_size(*size, files.count - 1);
+                    base - semantics.expected_size(last, allocation)
+                }
+                _ => base,
+            }
+        })
+        .collect();
+    // the most a database grows between two shrinks, a shrink is skipped for a tick of growth
+    let ceilings: Vec<u64> = targets
+        .iter()
+        .map(|size| {
+            let base = semantics.expected_size(*size, allocation);
+            growth.shrink.map_or(u64::MAX, |shrink| {
+                base + (rate as f64 * shrink.interval.as_secs_f64()) as u64 + rate
+            })
+        })
+        .collect();
+
+    let start = SystemTime::now();
+    let mut since = start;
+    // the growth is followed from the first measurement and from every shrink
+    let mut anchors: Vec<Option<(u64, u64)>> = vec![None; layout.databases.len()];
+    let mut previous_timestamp = 0;
+    let mut previous_sizes = vec![0; layout.databases.len()];
+    let mut shrinks = vec![0; layout.databases.len()];
+
+    while start.elapsed().unwrap_or_default() < window {
+        let (res, _) = get_fresh_measurement(since).await;
+        let timestamp = res[0]["timestamp"]
+            .as_u64()
+            .expect("Test failed: measurement without a timestamp");
+        since = UNIX_EPOCH + Duration::from_secs(timestamp);
+
+        for (index, database) in layout.databases.iter().enumerate() {
+            let size = res[0]["disk"][&database.key].as_u64().unwrap_or_else(|| {
+                panic!(
+                    "Test failed: {} data not found in measurements",
+                    database.key
+                )
+            });
+            println!(
+                "\t{} SIZE at {}: {}KB",
+                database.key,
+                timestamp,
+                bytes_to_kilobytes(size)
+            );
+            assert!(
+                size + error_margin >= floors[index],
+                "Test failed: {} shrunk below {}KB",
+                database.key,
+                bytes_to_kilobytes(floors[index])
+            );
+            assert!(
+                size <= ceilings[index].saturating_add(error_margin),
+                "Test failed: {} grew above {}KB, it was not shrunk",
+                database.key,
+                bytes_to_kilobytes(ceilings[index])
+            );
+
+            if let Some((anchor_timestamp, anchor_size)) = anchors[index] {
+                // measurements are timestamped in whole seconds and the load appends once a
+                // second, both may be off by a second
+                let tolerance = 2 * rate + error_margin;
+                let expected = anchor_size + rate * (timestamp - anchor_timestamp);
+                let followed_growth =
+                    expected + tolerance >= size && expected.saturating_sub(tolerance) <= size;
+                // after a shrink the database grew from its floor for at most the elapsed time
+                let followed_shrink = growth.shrink.is_some()
+                    && size < previous_sizes[index]
+                    && floors[index] + rate * (timestamp - previous_timestamp) + tolerance >= size;
+
+                if followed_shrink && !followed_growth {
+                    println!("\t{} SHRUNK", database.key);
+                    shrinks[index] += 1;
+                    anchors[index] = Some((timestamp, size));
+                } else {
+                    assert!(
+                        followed_growth,
+                        "Test failed: {} grew from {}KB to {}KB in {}s, expected {}KB",
+                        database.key,
+                        bytes_to_kilobytes(anchor_size),
+                        bytes_to_kilobytes(size),
+                        timestamp - anchor_timestamp,
+                        bytes_to_kilobytes(expected)
+                    );
+                }
+            } else {
+                anchors[index] = Some((timestamp, size));
+            }
+            previous_sizes[index] = size;
+        }
+        previous_timestamp = timestamp;
+        println!();
+    }
 
-        println!("=== OK ===\n");
-    } else {
-        panic!("Test failed: contextStorage data not found in measurements")
+    if let Some(shrink) = growth.shrink {
+        for (index, database) in layout.databases.iter().enumerate() {
+            // a shrink within the error margin cannot be told apart from the growth
+            let dropped = ceilings[index] - rate - floors[index];
+            if dropped > 2 * (rate + error_margin) {
+                assert!(
+                    shrinks[index] > 0,
+                    "Test failed: {} was not seen shrinking within {:?}",
+                    database.key,
+                    shrink.interval + DISK_GROWTH_WINDOW
+                );
+            }
+        }
+    }
+
+    println!("=== OK ===\n");
+}
+
+/// Compares every measurement of the monitoring history sampled during the replay of the `trace`
+/// from `start` against the sample replayed at that time, once the replay ended
+pub async fn test_trace(
+    trace: &Trace,
+    speed: f64,
+    start: SystemTime,
+    allocation: DiskAllocation,
+    semantics: DiskSizeSemantics,
+    layout: &DiskLayout,
+) {
+    println!("=== TESTING MEASUREMENT HISTORY AGAINST THE TRACE ===\n\n");
+
+    let cpu_error_margin = 10.0;
+    // 30 MB, see test_memory
+    let memory_error_margin = 31_457_280.0;
+    // 30 KB/s
+    let io_error_margin = 30_720.0;
+    // 30 KB, the replay grows a single file per database
+    let disk_error_margin = match semantics {
+        DiskSizeSemantics::Apparent => 30_720.0,
+        DiskSizeSemantics::Allocated => (30_720 + BLOCK_SIZE) as f64,
+    };
+
+    println!("\tTRACE: {}, REPLAYED {}x FASTER", trace.name, speed);
+    println!("\tSETTLING TIME: {:?}\n", TRACE_SETTLE);
+
+    let replay_end = start + trace.duration().div_f64(speed) + TRACE_SETTLE;
+    if let Ok(remaining) = replay_end.duration_since(SystemTime::now()) {
+        println!("\tWAITING {:?} FOR THE REPLAY TO END\n", remaining);
+        sleep(remaining).await;
     }
 
-    if let Some(diks_data) = res[0]["disk"]["blockStorage"].as_u64() {
+    // measurements are timestamped in unix seconds, there is at most one every second
+    let limit = profile::elapsed_since(start).as_secs() + 1;
+    let res = get_measurement_history(limit).await;
+    let mut measurements: Vec<&serde_json::Value> = res
+        .as_array()
+        .map(|measurements| measurements.iter().collect())
+        .unwrap_or_default();
+    measurements.sort_by_key(|measurement| measurement["timestamp"].as_u64());
+
+    let mut compared = 0;
+    for measurement in measurements {
+        let timestamp = match measurement["timestamp"].as_u64() {
+            Some(timestamp) => timestamp,
+            None => continue,
+        };
+        let elapsed = match (UNIX_EPOCH + Duration::from_secs(timestamp)).duration_since(start) {
+            Ok(elapsed) => elapsed,
+            Err(_) => continue,
+        };
+        let sample = trace.at(elapsed.mul_f64(speed));
+        if elapsed < sample.offset.div_f64(speed) + TRACE_SETTLE {
+            continue;
+        }
         println!(
-            "\tblockStorage SIZE at: {}MB\n",
-            bytes_to_megabytes(diks_data)
+            "\tMEASUREMENT AT {}, TRACE AT {:?}",
+            timestamp, sample.offset
         );
-        assert!(target + error_margin >= diks_data);
-        assert!(target - error_margin <= diks_data);
 
-        println!("=== OK ===\n");
-    } else {
-        panic!("Test failed: blockStorage data not found in measurements")
+        let tasks = &measurement["cpu"]["node"]["taskThreads"];
+        for name in trace.thread_names() {
+            let target = sample.thread_target(&name);
+            // the trace names the threads like the monitoring, truncated, so they match exactly
+            let thread_cpu = tasks.get(&name).and_then(|thread_cpu| thread_cpu.as_f64());
+            // an idle thread may be left out of the measurement
+            if thread_cpu.is_some() || target > cpu_error_margin {
+                assert_traced(&name, timestamp, target, thread_cpu, cpu_error_margin);
+            }
+        }
+        if let Some(target) = sample.memory {
+            let memory_data = measurement["memory"]["node"].as_f64();
+            assert_traced(
+                "MEMORY",
+                timestamp,
+                target as f64,
+                memory_data,
+                memory_error_margin,
+            );
+        }
+        if let Some(target) = sample.io_read {
+            let io_data = measurement["io"]["node"]["readBytesPerSec"].as_f64();
+            assert_traced(
+                "DISK READ",
+                timestamp,
+                target as f64,
+                io_data,
+                io_error_margin,
+            );
+        }
+        if let Some(target) = sample.io_written {
+            let io_data = measurement["io"]["node"]["writtenBytesPerSec"].as_f64();
+            assert_traced(
+                "DISK WRITE",
+                timestamp,
+                target as f64,
+                io_data,
+                io_error_margin,
+            );
+        }
+        for (name, size) in &sample.disk {
+            let database = layout.find(name).unwrap_or_else(|e| panic!("{}", e));
+            let target = semantics.expected_size(*size, allocation);
+            let disk_data = measurement["disk"][&database.key].as_f64();
+            assert_traced(
+                &database.key,
+                timestamp,
+                target as f64,
+                disk_data,
+                disk_error_margin,
+            );
+        }
+        compared += 1;
     }
 
-    if let Some(diks_data) = res[0]["disk"]["mainDb"].as_u64() {
-        println!("\tmainDb SIZE at: {}MB\n", bytes_to_megabytes(diks_data));
-        assert!(target + error_margin >= diks_data);
-        assert!(target - error_margin <= diks_data);
+    println!("\tCOMPARED {} MEASUREMENTS\n", compared);
+    assert!(
+        compared > 0,
+        "Test failed: no measurement sampled {:?} after the replay of a trace sample",
+        TRACE_SETTLE
+    );
+    println!("=== OK ===\n");
+}
 
-        println!("=== OK ===\n");
-    } else {
-        panic!("Test failed: mainDb data not found in measurements")
+/// Asserts that the `measured` value of the measurement at `timestamp` is within the error margin
+/// of the `target` of the trace
+fn assert_traced(
+    name: &str,
+    timestamp: u64,
+    target: f64,
+    measured: Option<f64>,
+    error_margin: f64,
+) {
+    let measured = measured.unwrap_or_else(|| {
+        panic!(
+            "Test failed: No {} data found in the measurement at {}",
+            name, timestamp
+        )
+    });
+    println!("\t\t{} TARGET: {}, at: {}", name, target, measured);
+    assert!(
+        (measured - target).abs() <= error_margin,
+        "Test failed: {} at {} is not within the error margin of the trace",
+        name,
+        timestamp
+    );
+}
+
+/// Polls the monitoring until it publishes a measurement sampled after `since`, returns it with
+/// the time waited for it, which includes the time the monitoring took to measure
+async fn get_fresh_measurement(since: SystemTime) -> (serde_json::Value, Duration) {
+    let since_secs = since
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+
+    for _ in 0..FRESH_MEASUREMENT_RETRIES {
+        let res = get_latest_measurement(FRESH_MEASUREMENT_POLL).await;
+        // measurements are timestamped in unix seconds
+        if res[0]["timestamp"].as_u64().is_some_and(|t| t > since_secs) {
+            let waited = SystemTime::now().duration_since(since).unwrap_or_default();
+            return (res, waited);
+        }
     }
+    panic!("Test failed: no fresh measurement published")
 }
 
 pub async fn get_latest_measurement(delay: Duration) -> serde_json::Value {
-    let retries = 100;
-
     // Network for example needs a few seconds to stabilize on the download speed, so get later measuremnt
     sleep(delay).await;
 
+    get_measurement_history(1).await
+}
+
+/// Returns the latest `limit` measurements of the monitoring
+pub async fn get_measurement_history(limit: u64) -> serde_json::Value {
+    let retries = 100;
+
     for _ in 0..retries {
-        match reqwest::get("http://127.0.0.1:38732/resources/tezedge?limit=1").await {
+        let url = format!("http://127.0.0.1:38732/resources/tezedge?limit={}", limit);
+        match reqwest::get(&url).await {
             Ok(result) => return result.json().await.unwrap_or_default(),
             Err(_) => {
                 println!("\tMonitoring not yet ready, retrying in 5s");
@@ -0,0 +1,300 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::configuration::{ThreadLoad, MAX_THREAD_NAME_LEN};
+use crate::disk::DiskLayout;
+use crate::quantity;
+
+/// Resource usage of a node recorded over time, replayed by the trace load and compared to the
+/// monitoring history by the trace test
+#[derive(Clone, Debug)]
+pub struct Trace {
+    pub name: String,
+    /// Samples ordered by their offset, the first one at offset 0
+    pub samples: Vec<TraceSample>,
+}
+
+/// Resource usage of a node at a point of a trace, held up to the next sample
+///
+/// A value missing from a sample is carried over from the previous one, it is `None` only until it
+/// first appears in the trace
+#[derive(Clone, Debug, Default)]
+pub struct TraceSample {
+    /// Time since the first sample of the trace
+    pub offset: Duration,
+    /// Cpu load of every thread in percent of one CPU, a thread that exited is idle
+    pub threads: Vec<ThreadLoad>,
+    /// Memory of the node in bytes
+    pub memory: Option<u64>,
+    /// Bytes read from the storage per second
+    pub io_read: Option<u64>,
+    /// Bytes written to the storage per second
+    pub io_written: Option<u64>,
+    /// Size of every database in bytes, by its name
+    pub disk: Vec<(String, u64)>,
+}
+
+impl TraceSample {
+    /// Returns the cpu load of the thread `name`
+    pub fn thread_target(&self, name: &str) -> f64 {
+        self.threads
+            .iter()
+            .find(|thread| thread.name == name)
+            .map_or(0.0, |thread| thread.target)
+    }
+
+    /// Returns the size of the database `name`, when the trace recorded it so far
+    pub fn disk_size(&self, name: &str) -> Option<u64> {
+        self.disk
+            .iter()
+            .find(|(database, _)| database == name)
+            .map(|(_, size)| *size)
+    }
+}
+
+impl Trace {
+    /// Returns the sample in effect `offset` after the start of the trace, the last one is held
+    /// after the end of the trace
+    pub fn at(&self, offset: Duration) -> &TraceSample {
+        let index = self
+            .samples
+            .partition_point(|sample| sample.offset <= offset);
+        &self.samples[index.saturating_sub(1)]
+    }
+
+    /// Returns the offset of the last sample
+    pub fn duration(&self) -> Duration {
+        self.samples
+            .last()
+            .map(|sample| sample.offset)
+            .unwrap_or_default()
+    }
+
+    /// Returns the names of every thread with a cpu load in the trace
+    pub fn thread_names(&self) -> BTreeSet<String> {
+        self.samples
+            .iter()
+            .flat_map(|sample| sample.threads.iter().map(|thread| thread.name.clone()))
+            .collect()
+    }
+
+    /// Returns the names of every database with a size in the trace
+    pub fn disk_names(&self) -> BTreeSet<String> {
+        self.samples
+            .iter()
+            .flat_map(|sample| sample.disk.iter().map(|(name, _)| name.clone()))
+            .collect()
+    }
+
+    /// Checks that every database of the trace is in the `layout`
+    pub fn check_layout(&self, layout: &DiskLayout) -> Result<(), String> {
+        for name in self.disk_names() {
+            layout.find(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the monitoring export, a json list of measurements as served by
+    /// `/resources/tezedge`, in any order
+    fn from_json(content: &str) -> Result<Vec<(f64, TraceSample)>, String> {
+        let measurements: Vec<Value> = serde_json::from_str(content).map_err(|e| e.to_string())?;
+
+        let mut samples = measurements
+            .iter()
+            .map(|measurement| {
+                let timestamp = match &measurement["timestamp"] {
+                    Value::Number(number) => parse_timestamp(&number.to_string())?,
+                    _ => return Err("Measurement without a timestamp".to_string()),
+                };
+                let cpu = &measurement["cpu"]["node"];
+                // without per thread data a single test_thread carries the collective load
+                let threads = match (cpu["taskThreads"].as_object(), cpu["collective"].as_f64()) {
+                    (Some(tasks), _) => tasks
+                        .iter()
+                        .filter_map(|(name, target)| Some(thread_load(name, target.as_f64()?)))
+                        .collect(),
+                    (None, Some(collective)) => vec![thread_load("test_thread", collective)],
+                    (None, None) => Vec::new(),
+                };
+                let disk = measurement["disk"]
+                    .as_object()
+                    .map(|databases| {
+                        databases
+                            .iter()
+                            .filter_map(|(name, size)| Some((name.clone(), size.as_u64()?)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                Ok((
+                    timestamp,
+                    TraceSample {
+                        offset: Duration::ZERO,
+                        threads,
+                        memory: measurement["memory"]["node"].as_u64(),
+                        io_read: measurement["io"]["node"]["readBytesPerSec"].as_u64(),
+                        io_written: measurement["io"]["node"]["writtenBytesPerSec"].as_u64(),
+                        disk,
+                    },
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // a measurement lists every running thread, one that is not listed exited, it is idle
+        // rather than carried over, unless the measurement has no cpu data at all
+        let names: BTreeSet<String> = samples
+            .iter()
+            .flat_map(|(_, sample)| sample.threads.iter().map(|thread| thread.name.clone()))
+            .collect();
+        for (_, sample) in samples
+            .iter_mut()
+            .filter(|(_, sample)| !sample.threads.is_empty())
+        {
+            for name in &names {
+                if sample.threads.iter().all(|thread| &thread.name != name) {
+                    sample.threads.push(thread_load(name, 0.0));
+                }
+            }
+        }
+        Ok(samples)
+    }
+
+    /// Reads a csv export with a header line naming the columns, `timestamp` in unix seconds and
+    /// any of `cpu.THREAD`, `memory`, `io.read`, `io.written` and `disk.DATABASE`, an empty cell
+    /// is a missing value
+    fn from_csv(content: &str) -> Result<Vec<(f64, TraceSample)>, String> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| "No header line".to_string())?;
+        let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+        if !columns.contains(&"timestamp") {
+            return Err("No timestamp column".to_string());
+        }
+
+        lines
+            .map(|(index, line)| {
+                let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+                if cells.len() != columns.len() {
+                    return Err(format!(
+                        "Line {} has {} cells, the header has {}",
+                        index + 1,
+                        cells.len(),
+                        columns.len()
+                    ));
+                }
+
+                let mut timestamp = None;
+                let mut sample = TraceSample::default();
+                for (column, cell) in columns.iter().zip(cells) {
+                    let invalid = |e: String| format!("Line {}, {}: {}", index + 1, column, e);
+                    match *column {
+                        _ if cell.is_empty() => {}
+                        "timestamp" => timestamp = Some(parse_timestamp(cell).map_err(invalid)?),
+                        column => read_cell(&mut sample, column, cell).map_err(invalid)?,
+                    }
+                }
+                // a sample cannot be placed without its timestamp, it is never carried over
+                let timestamp =
+                    timestamp.ok_or_else(|| format!("Line {} has no timestamp", index + 1))?;
+                Ok((timestamp, sample))
+            })
+            .collect()
+    }
+}
+
+/// Unix seconds of a sample, the offsets of the samples are durations so they cannot be negative
+fn parse_timestamp(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|timestamp| timestamp.is_finite() && *timestamp >= 0.0)
+        .ok_or_else(|| format!("Was expecting non-negative unix seconds, got {}", value))
+}
+
+/// Reads the `cell` of a csv trace into the `column` of the `sample`
+fn read_cell(sample: &mut TraceSample, column: &str, cell: &str) -> Result<(), String> {
+    match column.split_once('.') {
+        None if column == "memory" => sample.memory = Some(quantity::parse_size(cell)?),
+        Some(("io", "read")) => sample.io_read = Some(quantity::parse_rate(cell)?),
+        Some(("io", "written")) => sample.io_written = Some(quantity::parse_rate(cell)?),
+        Some(("cpu", name)) if !name.is_empty() => sample
+            .threads
+            .push(thread_load(name, quantity::parse_percent(cell)?)),
+        Some(("disk", name)) if !name.is_empty() => sample
+            .disk
+            .push((name.to_string(), quantity::parse_size(cell)?)),
+        _ => {
+            return Err(format!(
+                "Unknown column, was expecting timestamp, cpu.THREAD, memory, io.read, io.written or disk.DATABASE, got {}",
+                column
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// A thread named like the monitoring names it, truncated like Linux truncates it
+fn thread_load(name: &str, target: f64) -> ThreadLoad {
+    let mut len = name.len().min(MAX_THREAD_NAME_LEN);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    ThreadLoad {
+        name: name[..len].to_string(),
+        target,
+    }
+}
+
+impl FromStr for Trace {
+    type Err = String;
+
+    /// Reads a trace from a json monitoring export or a csv file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let content =
+            fs::read_to_string(s).map_err(|e| format!("Cannot read trace file {}: {}", s, e))?;
+        let mut samples = if content.trim_start().starts_with('[') {
+            Trace::from_json(&content)
+        } else {
+            Trace::from_csv(&content)
+        }
+        .map_err(|e| format!("Invalid trace file {}: {}", s, e))?;
+
+        if samples.is_empty() {
+            return Err(format!("Trace file {} has no samples", s));
+        }
+        samples.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        // carry the missing values over from the previous sample
+        let first = samples[0].0;
+        let mut previous = TraceSample::default();
+        for (timestamp, sample) in samples.iter_mut() {
+            sample.offset = Duration::try_from_secs_f64(*timestamp - first)
+                .map_err(|_| format!("Trace file {} spans more than {}s", s, u64::MAX))?;
+            for thread in &previous.threads {
+                if sample.threads.iter().all(|other| other.name != thread.name) {
+                    sample.threads.push(thread.clone());
+                }
+            }
+            sample.memory = sample.memory.or(previous.memory);
+            sample.io_read = sample.io_read.or(previous.io_read);
+            sample.io_written = sample.io_written.or(previous.io_written);
+            for (name, size) in &previous.disk {
+                if sample.disk_size(name).is_none() {
+                    sample.disk.push((name.clone(), *size));
+                }
+            }
+            previous = sample.clone();
+        }
+
+        Ok(Self {
+            name: s.to_string(),
+            samples: samples.into_iter().map(|(_, sample)| sample).collect(),
+        })
+    }
+}